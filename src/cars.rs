@@ -0,0 +1,124 @@
+use crate::game_data::{Entry, TuningTable};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// The game's names for cars are long and sometimes cryptic, so the board shows a shorter name
+// from a table of cars, along with the manufacturer, class and a colour for the car. A table
+// comes with the overlay and can be added to or overridden in the config.
+//
+// Cars are keyed by the short name the game gives them, or by `id:<n>` for the row of the car in
+// the game's table of cars, for cars whose names are the same or vary between releases.
+
+const BUNDLED: &str = include_str!("../resources/cars.toml");
+
+/// What is known about a car, all optional so the config can change just part of it
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct CarInfo {
+    /// shown instead of the game's name
+    pub name: Option<String>,
+    pub manufacturer: Option<String>,
+    pub class: Option<String>,
+    /// livery or team colour as `#rrggbb`
+    pub colour: Option<String>,
+}
+
+/// How a car is shown, with everything that could be looked up
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarLabel {
+    pub name: String,
+    pub manufacturer: Option<String>,
+    pub class: Option<String>,
+    pub colour: Option<[u8; 3]>,
+}
+
+impl CarLabel {
+    /// The colour as `#rrggbb`, for HTML and JSON
+    pub fn colour_hex(&self) -> Option<String> {
+        self.colour.map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+    }
+
+    pub fn colour_rgba(&self) -> Option<[f32; 4]> {
+        self.colour.map(|[r, g, b]| [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0])
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct CarTable {
+    cars: BTreeMap<String, CarInfo>,
+}
+
+pub struct CarDatabase {
+    bundled: BTreeMap<String, CarInfo>,
+    /// from the config, taking precedence over the bundled table a field at a time
+    overrides: BTreeMap<String, CarInfo>,
+}
+
+impl CarDatabase {
+    pub fn new(overrides: &BTreeMap<String, CarInfo>) -> Self {
+        let bundled = match parse_table(BUNDLED) {
+            Ok(table) => table,
+            Err(e) => {
+                log::error!("{:#}", e);
+                BTreeMap::new()
+            }
+        };
+        for (key, info) in bundled.iter().chain(overrides) {
+            if let Some(colour) = &info.colour {
+                if parse_colour(colour).is_none() {
+                    log::warn!("colour {:?} for {} isn't in the form #rrggbb", colour, key);
+                }
+            }
+        }
+        CarDatabase { bundled, overrides: overrides.clone() }
+    }
+
+    /// How to show the car with this entry, whose name has been decoded as `game_name`
+    pub fn label(&self, entry: &Entry, game_name: &str) -> CarLabel {
+        let id = entry.tuning(TuningTable::GENERIC_CAR).map(|id| format!("id:{}", id));
+        let keys: Vec<&str> = id.as_deref().into_iter().chain(Some(game_name)).collect();
+        let found: Vec<&CarInfo> = [&self.overrides, &self.bundled]
+            .iter()
+            .flat_map(|table| keys.iter().filter_map(move |&key| table.get(key)))
+            .collect();
+        let first = |field: fn(&CarInfo) -> &Option<String>| {
+            found.iter().find_map(|info| field(info).clone())
+        };
+        CarLabel {
+            name: first(|info| &info.name).unwrap_or_else(|| shorten(game_name)),
+            manufacturer: first(|info| &info.manufacturer),
+            class: first(|info| &info.class),
+            colour: first(|info| &info.colour).as_deref().and_then(parse_colour),
+        }
+    }
+}
+
+fn parse_table(text: &str) -> Result<BTreeMap<String, CarInfo>> {
+    let table: CarTable = toml::from_str(text).context("parsing the bundled table of cars")?;
+    Ok(table.cars)
+}
+
+/// The game's name without the chassis codes in brackets, e.g. `(GC8)`
+fn shorten(game_name: &str) -> String {
+    let mut short = String::with_capacity(game_name.len());
+    let mut depth = 0;
+    for c in game_name.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ if depth == 0 => short.push(c),
+            _ => {}
+        }
+    }
+    short.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    let hex = colour.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
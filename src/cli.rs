@@ -0,0 +1,284 @@
+use crate::{
+    config::Config,
+    obs::ObsConfig,
+    processes::{self, Target, PCSX2_PROCESS_NAMES},
+    scan_memory::MAX_NEEDLE_LEN,
+    server::ServerConfig,
+    telemetry::{TelemetryConfig, TelemetryFormat},
+    udp::UdpConfig,
+};
+use anyhow::{bail, Result};
+use log::LevelFilter;
+use std::{path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "timing", about = "Live timing for Gran Turismo 4 running in PCSX2")]
+pub struct Args {
+    /// config file, which has to exist if given. gt4timing.toml if not given, if it exists
+    #[structopt(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// off, error, warn, info, debug or trace
+    #[structopt(long, global = true, default_value = "debug")]
+    pub log_level: LevelFilter,
+    /// the overlay window if not given
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Shows the overlay in a window
+    Live {
+        #[structopt(flatten)]
+        memory: MemoryArgs,
+        #[structopt(flatten)]
+        window: WindowArgs,
+        #[structopt(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Shows a timing tower in the terminal instead of a window, for one PCSX2 or several
+    Headless {
+        #[structopt(flatten)]
+        memory: MemoryArgs,
+        #[structopt(flatten)]
+        refresh: RefreshArgs,
+        #[structopt(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Records everything read from PCSX2 while showing the timing tower, for `replay`
+    Record {
+        /// file to record to
+        output: PathBuf,
+        #[structopt(flatten)]
+        process: ProcessArgs,
+        #[structopt(flatten)]
+        refresh: RefreshArgs,
+        #[structopt(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Plays back a recording, one recorded sample per frame
+    Replay {
+        /// file made by `record`
+        recording: PathBuf,
+        /// show the timing tower in the terminal instead of the overlay
+        #[structopt(long)]
+        headless: bool,
+        #[structopt(flatten)]
+        window: WindowArgs,
+        #[structopt(flatten)]
+        refresh: RefreshArgs,
+        #[structopt(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Searches the memory of PCSX2 for a value, printing where it is in the PS2's memory
+    Scan {
+        #[structopt(flatten)]
+        process: ProcessArgs,
+        /// what to look for
+        #[structopt(flatten)]
+        needle: Needle,
+    },
+    /// Lists the PCSX2 processes running, with the game in each
+    List {
+        #[structopt(flatten)]
+        names: ProcessNames,
+    },
+    /// Saves a copy of EE RAM from PCSX2, which can be read back with `--image`
+    Dump {
+        /// file to save to
+        output: PathBuf,
+        #[structopt(flatten)]
+        process: ProcessArgs,
+    },
+    /// Converts a telemetry session to MoTeC i2 files
+    ExportMotec {
+        /// CSV or JSON lines file logged by the telemetry output
+        session: PathBuf,
+        /// defaults to the session's path without its extension
+        out_dir: Option<PathBuf>,
+    },
+}
+
+/// Which PCSX2 to read
+#[derive(StructOpt, Debug)]
+pub struct ProcessArgs {
+    /// PID of the PCSX2 process, which is otherwise found by name. Can be given more than once
+    /// with `headless`.
+    #[structopt(long, number_of_values = 1)]
+    pub pid: Vec<usize>,
+    /// which PCSX2 to read, counting from 0 in the order of `timing list`. Can be given more than
+    /// once with `headless`.
+    #[structopt(long, number_of_values = 1)]
+    pub index: Vec<usize>,
+    /// read every PCSX2 running, with `headless`
+    #[structopt(long, conflicts_with_all = &["pid", "index"])]
+    pub all: bool,
+    #[structopt(flatten)]
+    pub names: ProcessNames,
+}
+
+impl ProcessArgs {
+    /// Each PCSX2 to read
+    pub fn targets(&self) -> Result<Vec<Target>> {
+        if self.all {
+            let found = processes::list_pcsx2_processes(&self.names.names());
+            if found.is_empty() {
+                bail!("no PCSX2 processes found named {}", self.names.names().join(", "));
+            }
+            return Ok(found.iter().map(|process| Target::Pid(process.pid)).collect());
+        }
+        let targets: Vec<_> = self
+            .pid
+            .iter()
+            .map(|&pid| Target::Pid(pid))
+            .chain(self.index.iter().map(|&index| Target::Index(index)))
+            .collect();
+        Ok(if targets.is_empty() { vec![Target::First] } else { targets })
+    }
+
+    /// The PCSX2 to read, for commands that only read one
+    pub fn target(&self) -> Result<Target> {
+        match self.targets()?.as_slice() {
+            [target] => Ok(*target),
+            _ => bail!("only one PCSX2 can be read by this command, so choose one PID or index"),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ProcessNames {
+    /// name of the PCSX2 process, which can be given more than once. pcsx2 if not given.
+    #[structopt(long = "process-name", number_of_values = 1)]
+    pub process_names: Vec<String>,
+}
+
+impl ProcessNames {
+    pub fn names(&self) -> Vec<String> {
+        if self.process_names.is_empty() {
+            PCSX2_PROCESS_NAMES.iter().map(|&name| name.to_owned()).collect()
+        } else {
+            self.process_names.clone()
+        }
+    }
+}
+
+/// Where the game is read from
+#[derive(StructOpt, Debug)]
+pub struct MemoryArgs {
+    #[structopt(flatten)]
+    pub process: ProcessArgs,
+    /// read a dump of EE RAM instead of PCSX2
+    #[structopt(long, conflicts_with = "fake")]
+    pub image: Option<PathBuf>,
+    /// run a made up race instead of reading PCSX2
+    #[structopt(long)]
+    pub fake: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WindowArgs {
+    #[structopt(long, default_value = "400")]
+    pub width: f32,
+    #[structopt(long, default_value = "300")]
+    pub height: f32,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RefreshArgs {
+    /// how often the timing tower is redrawn. The game is sampled 60 times a second regardless.
+    #[structopt(long, default_value = "4", parse(try_from_str = parse_hz))]
+    pub refresh_hz: f32,
+}
+
+/// Outputs to turn on, with their other settings taken from the config file
+#[derive(StructOpt, Debug)]
+pub struct SinkArgs {
+    /// log telemetry as csv or json
+    #[structopt(long)]
+    pub telemetry: Option<TelemetryFormat>,
+    /// serve the race over HTTP and websockets at this address
+    #[structopt(long)]
+    pub server: Option<String>,
+    /// send UDP telemetry packets to this address
+    #[structopt(long)]
+    pub udp: Option<String>,
+    /// write standings for OBS to this directory
+    #[structopt(long)]
+    pub obs: Option<PathBuf>,
+}
+
+impl SinkArgs {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(format) = self.telemetry {
+            let telemetry = config.telemetry.get_or_insert_with(TelemetryConfig::default);
+            telemetry.format = format;
+        }
+        if let Some(address) = &self.server {
+            config.server.get_or_insert_with(ServerConfig::default).address = address.clone();
+        }
+        if let Some(target) = &self.udp {
+            config.udp.get_or_insert_with(UdpConfig::default).target = target.clone();
+        }
+        if let Some(directory) = &self.obs {
+            config.obs.get_or_insert_with(ObsConfig::default).directory = directory.clone();
+        }
+    }
+}
+
+/// The bytes to search for, given in one of several forms
+#[derive(StructOpt, Debug)]
+pub struct Needle {
+    /// bytes in hex, e.g. 0000c07f
+    #[structopt(long, required_unless_one = &["f32", "text"])]
+    pub hex: Option<HexBytes>,
+    /// a little endian float
+    #[structopt(long)]
+    pub f32: Option<f32>,
+    /// ASCII text, e.g. part of a car name
+    #[structopt(long)]
+    pub text: Option<String>,
+}
+
+impl Needle {
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        let bytes = if let Some(HexBytes(bytes)) = &self.hex {
+            bytes.clone()
+        } else if let Some(value) = self.f32 {
+            value.to_le_bytes().to_vec()
+        } else {
+            self.text.as_deref().unwrap_or_default().as_bytes().to_vec()
+        };
+        if bytes.is_empty() || bytes.len() > MAX_NEEDLE_LEN {
+            bail!("can only search for 1 to {} bytes, not {}", MAX_NEEDLE_LEN, bytes.len());
+        }
+        Ok(bytes)
+    }
+}
+
+/// A rate that something can be done at, so above 0
+fn parse_hz(s: &str) -> Result<f32> {
+    let hz: f32 = s.parse()?;
+    if !(hz > 0.0 && hz.is_finite()) {
+        bail!("expected a rate above 0");
+    }
+    Ok(hz)
+}
+
+#[derive(Debug)]
+pub struct HexBytes(Vec<u8>);
+
+impl FromStr for HexBytes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() || s.len() % 2 != 0 {
+            bail!("expected an even number of hex digits");
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| Ok(u8::from_str_radix(s.get(i..i + 2).unwrap_or("?"), 16)?))
+            .collect::<Result<_>>()
+            .map(HexBytes)
+    }
+}
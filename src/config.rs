@@ -0,0 +1,79 @@
+use crate::{
+    cars::CarInfo, game_data::MAX_CARS, obs::ObsConfig, ps2_types::TextEncoding,
+    server::ServerConfig, telemetry::TelemetryConfig, tracks::TrackInfo, udp::UdpConfig,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+const DEFAULT_CONFIG_PATH: &str = "gt4timing.toml";
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// sector boundaries as fractions of the lap, keyed by track length in whole metres, e.g.
+    /// `"5807" = [0.3, 0.7]` splits that track into three sectors
+    pub sectors: BTreeMap<String, Vec<f32>>,
+    /// slot of the player's car, counting from 0, if it can't be told from the pedals
+    pub player_slot: Option<usize>,
+    /// names, sectors and outlines of tracks, keyed by track length in whole metres, added to or
+    /// replacing the table that comes with the overlay
+    pub tracks: BTreeMap<String, TrackInfo>,
+    /// how car names are encoded, `"shift_jis"` or `"windows-1252"`, if it can't be told from
+    /// the release of the game
+    pub text_encoding: Option<TextEncoding>,
+    /// names, classes and colours of cars, keyed by the game's short name or `"id:<n>"`, added
+    /// to or overriding the table that comes with the overlay
+    pub cars: BTreeMap<String, CarInfo>,
+    /// telemetry is only logged if this section is present
+    pub telemetry: Option<TelemetryConfig>,
+    /// the race server only runs if this section is present
+    pub server: Option<ServerConfig>,
+    /// UDP telemetry packets are only sent if this section is present
+    pub udp: Option<UdpConfig>,
+    /// standings for OBS are only written if this section is present
+    pub obs: Option<ObsConfig>,
+}
+
+impl Config {
+    /// Reads `gt4timing.toml`, falling back to the defaults if there isn't one
+    pub fn load_default() -> Result<Config> {
+        let path = Path::new(DEFAULT_CONFIG_PATH);
+        if !path.exists() {
+            log::info!("no config file at {}, using defaults", path.display());
+            return Ok(Config::default());
+        }
+        Config::load(path)
+    }
+
+    /// Reads a config file, which has to exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        let config: Config = toml::from_str(&text)
+            .with_context(|| format!("parsing config file {}", path.display()))?;
+        config.validate().with_context(|| format!("checking config file {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Rejects values that would make no sense or break something later
+    fn validate(&self) -> Result<()> {
+        if let Some(slot) = self.player_slot {
+            if slot >= MAX_CARS {
+                bail!("player_slot is {}, but slots only go up to {}", slot, MAX_CARS - 1);
+            }
+        }
+        if let Some(telemetry) = &self.telemetry {
+            if !(telemetry.sample_rate_hz > 0.0 && telemetry.sample_rate_hz.is_finite()) {
+                bail!("telemetry sample_rate_hz must be above 0, not {}", telemetry.sample_rate_hz);
+            }
+        }
+        if let Some(udp) = &self.udp {
+            if !(udp.rate_hz > 0.0 && udp.rate_hz.is_finite()) {
+                bail!("udp rate_hz must be above 0, not {}", udp.rate_hz);
+            }
+        }
+        Ok(())
+    }
+}
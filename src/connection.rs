@@ -0,0 +1,120 @@
+use crate::{
+    processes::{self, Pcsx2Process, Target},
+    ps2_types::{ConnectionStatus, Ps2Address, Ps2Error, Ps2Memory, Ps2Result, Ps2SeparateProcess},
+};
+use anyhow::{bail, Result};
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+use sysinfo::{System, SystemExt};
+
+/// how long to wait between looking for PCSX2
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Attached {
+    process: Pcsx2Process,
+    memory: Ps2SeparateProcess,
+}
+
+/// PCSX2 running as another process, which is attached to when it starts and reattached to if it
+/// restarts. One chosen by PID is lost for good when it exits. Reads fail while it isn't running.
+pub struct Pcsx2Connection {
+    target: Target,
+    names: Vec<String>,
+    attached: Option<Attached>,
+    /// set when the process chosen by PID has gone
+    lost: bool,
+    /// set by a failed read, which is how we notice PCSX2 has gone
+    read_failed: Cell<bool>,
+    last_attempt: Option<Instant>,
+    system: System,
+}
+
+impl Pcsx2Connection {
+    pub fn new(target: Target, names: Vec<String>) -> Self {
+        Pcsx2Connection {
+            target,
+            names,
+            attached: None,
+            lost: false,
+            read_failed: Cell::new(false),
+            last_attempt: None,
+            system: System::new(),
+        }
+    }
+
+    /// Attaches to PCSX2 if it has started, or lets go of it if it has exited. Called before
+    /// each sample.
+    pub fn poll(&mut self) {
+        if let Some(attached) = &self.attached {
+            if !self.read_failed.replace(false) {
+                return;
+            }
+            let pid = attached.process.pid;
+            if processes::find_process(&mut self.system, pid).is_some() {
+                return;
+            }
+            log::warn!("PCSX2 process {} has exited", pid);
+            self.attached = None;
+        }
+        if self.lost {
+            return;
+        }
+        if self.last_attempt.map_or(false, |last| last.elapsed() < RETRY_INTERVAL) {
+            return;
+        }
+        self.last_attempt = Some(Instant::now());
+        if let Target::Pid(pid) = self.target {
+            if processes::find_process(&mut self.system, pid).is_none() {
+                log::warn!("PCSX2 process {} is gone, so it won't be read again", pid);
+                self.lost = true;
+                return;
+            }
+        }
+        match self.attach() {
+            Ok(attached) => {
+                log::info!("attached to {} ({})", attached.process.name, attached.process.pid);
+                self.attached = Some(attached);
+            }
+            Err(e) => log::debug!("{:#}", e),
+        }
+    }
+
+    fn attach(&mut self) -> Result<Attached> {
+        let process = match processes::find_target(&mut self.system, self.target, &self.names) {
+            Some(process) => process,
+            None => bail!("no PCSX2 process found"),
+        };
+        let handle = processes::open_process(process.pid)?;
+        Ok(Attached { process, memory: Ps2SeparateProcess { pcsx2_process_handle: handle } })
+    }
+}
+
+impl Ps2Memory for Pcsx2Connection {
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T> {
+        let attached = match &self.attached {
+            Some(attached) => attached,
+            None => return Err(Ps2Error::NotAttached),
+        };
+        let result = attached.memory.read_at(address);
+        // a bad address says nothing about whether PCSX2 is still there
+        if let Err(Ps2Error::ReadFailed { .. }) = result {
+            self.read_failed.set(true);
+        }
+        result
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        match &self.attached {
+            Some(attached) => ConnectionStatus::Attached {
+                pid: attached.process.pid,
+                name: attached.process.name.clone(),
+            },
+            None => match self.target {
+                Target::Pid(pid) if self.lost => ConnectionStatus::Lost { pid },
+                _ => ConnectionStatus::Waiting,
+            },
+        }
+    }
+}
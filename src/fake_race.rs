@@ -0,0 +1,61 @@
+use crate::{
+    game_data::{Addresses, Automobile, CarSpec, Entry, TimeMs},
+    ps2_types::{Ps2MemoryImage, Ps2Ptr},
+};
+use anyhow::Result;
+
+// A made up race written into a memory image where the game would keep it, so the overlay and
+// its outputs can be exercised without the emulator running.
+
+const TRACK_LENGTH: f32 = 4000.0;
+/// where the pointer chain to the track length leads in the fake image
+const FAKE_HEAP: u32 = 0x00100000;
+/// where the cars' specs are in the fake image
+const FAKE_CAR_SPECS: u32 = FAKE_HEAP + 0x1000;
+const GRID_SPACING_METRES: f32 = 10.0;
+const NAMES: [&str; 6] = ["Player Car", "AI Car A", "AI Car B", "AI Car C", "AI Car D", "AI Car E"];
+
+/// Writes the state of the fake race at the given race time
+pub fn write_fake_race(memory: &mut Ps2MemoryImage, race_time: TimeMs) -> Result<()> {
+    let addresses = Addresses::normal();
+
+    let chain = addresses.track_length.offsets();
+    let track = FAKE_HEAP + 0x100;
+    memory.write(chain[0], FAKE_HEAP)?;
+    memory.write(FAKE_HEAP + chain[1], track)?;
+    memory.write(track + chain[2], TRACK_LENGTH)?;
+
+    memory.write(addresses.race_time.address(), race_time)?;
+
+    let mut cars = [Automobile::zeroed(); 6];
+    for (i, car) in cars.iter_mut().enumerate() {
+        // the player starts at the back, the AI in slot order ahead of them
+        let grid_position = if i == 0 { NAMES.len() } else { i };
+        let lap_ms = 90_000.0 + 400.0 * i as f32;
+        let progress = 1.0 + race_time as f32 / lap_ms
+            - GRID_SPACING_METRES * grid_position as f32 / TRACK_LENGTH;
+        car.set_implicit_current_lap(progress.floor() as i16);
+        car.set_meters_driven_in_current_lap(progress.fract() * TRACK_LENGTH);
+        car.set_throttle_pedal(1.0);
+        car.set_throttle_actual(1.0);
+        car.set_gear(3 + (i % 3) as u8);
+        car.set_rpm(5000.0 + 2000.0 * progress.fract());
+        car.set_almost_rpm(car.rpm());
+        let car_spec_address = FAKE_CAR_SPECS + (i * CarSpec::SIZE) as u32;
+        car.set_car_spec(Ps2Ptr::new(car_spec_address));
+        let mut car_spec = CarSpec::zeroed();
+        car_spec.set_mass(1200.0 + 50.0 * i as f32);
+        memory.write(car_spec_address, car_spec)?;
+    }
+    memory.write(addresses.cars.address(), cars)?;
+    // the cars overwrite the block of NaNs that marks a loaded race
+    memory.write(addresses.nan_marker().address(), f32::NAN)?;
+
+    let mut entries = [Entry::zeroed(); 6];
+    for (entry, name) in entries.iter_mut().zip(NAMES.iter()) {
+        entry.set_car_name_short((*name).into());
+        entry.set_car_name((*name).into());
+    }
+    memory.write(addresses.entries.address(), entries)?;
+    Ok(())
+}
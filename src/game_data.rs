@@ -1,104 +1,92 @@
-use crate::ps2_types::{
-    Ps2InProcess, Ps2Memory, Ps2Ptr, Ps2PtrChain, Ps2SeparateProcess, Ps2String,
+use crate::{
+    cars::{CarDatabase, CarLabel},
+    config::Config,
+    game_struct,
+    game_version::GameVersion,
+    pits::{PitSummary, PitTracker},
+    player::{self, PlayerDetector, PlayerSource},
+    positions::PositionTracker,
+    projection::{self, Projection},
+    ps2_types::{
+        ConnectionStatus, Ps2InProcess, Ps2Memory, Ps2MemoryImage, Ps2Ptr, Ps2PtrChain, Ps2Result,
+        Ps2SeparateProcess, Ps2String, TextEncoding,
+    },
+    sectors::{self, CarSectors, SectorSplits},
+    sinks::{self, RaceSink},
+    tracks::{TrackDatabase, TrackInfo},
+    validation::{self, Validation},
 };
 use anyhow::{Context, Result};
-use derivative::Derivative;
 use ordered_float::OrderedFloat;
 use process_memory::ProcessHandle;
-use std::{collections::BTreeMap, mem::size_of};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    mem::size_of,
+    time::{Duration, Instant},
+};
 
-const MAX_CARS: usize = 6;
+pub const MAX_CARS: usize = 6;
 
 const BEFORE_NANS: usize = 140;
 
-// 4256 bytes
-// each struct starts a bit before the NaN block, guess 50 bytes
-#[repr(C)]
-#[derive(Derivative, Clone, Copy)]
-#[derivative(Debug)]
-pub struct Automobile {
-    pub race_organisation: Ps2Ptr<()>,
-    pub dynamics_conductor: Ps2Ptr<()>,
-    _unkptr1: Ps2Ptr<()>,
-    _unkptr2: Ps2Ptr<()>,
-    pub car_spec: Ps2Ptr<CarSpec>,
-    #[derivative(Debug = "ignore")]
-    _unk0: [u8; BEFORE_NANS - 20],
-    #[derivative(Debug = "ignore")]
-    nans: [u8; 64], // + 0
-    #[derivative(Debug = "ignore")]
-    _unk1276: [u8; 1216],
-    pub throttle_pedal: f32, // + 1280
-    pub brake1: f32,         // + 1284
-    #[derivative(Debug = "ignore")]
-    _unk4: [u8; 4],
-    pub throttle_actual: f32, // + 1292
-    #[derivative(Debug = "ignore")]
-    _unk100: [u8; 12],
-    pub meters_driven_in_current_lap: f32,
-    #[derivative(Debug = "ignore")]
-    _unkz: [u8; 4],
-    pub implicit_current_lap: i16, // from decompiler
-    #[derivative(Debug = "ignore")]
-    _unk100_2: [u8; 78],
-    pub almost_rpm: f32, // + 1396
-    #[derivative(Debug = "ignore")]
-    _unk32: [u8; 32],
-    pub gear: u8, // + 1432
-    #[derivative(Debug = "ignore")]
-    _unk274: [u8; 275],
-    pub rpm: f32, // + 1708
-    #[derivative(Debug = "ignore")]
-    _unk12: [u8; 12],
-    pub throttle3: f32, // + 1724
-    #[derivative(Debug = "ignore")]
-    _unk52: [u8; 52],
-    pub throttle4: f32, // + 1780
-    pub brake2: f32,    // + 1784
-    #[derivative(Debug = "ignore")]
-    _unk: [u8; 2328],
-}
+/// how long to wait before looking for the game's release again after not finding it, since it
+/// means searching all of EE RAM
+const GAME_VERSION_RETRY: Duration = Duration::from_secs(5);
 
-static_assertions::assert_eq_size!([u8; 4256], Automobile);
+// TODO: find fuel and tyre wear, which a fuel and tyre strategy for the player's car needs
+game_struct! {
+    /// A car in the race. Each one starts a bit before a block of NaNs, which is how a race
+    /// being loaded is recognised.
+    pub struct Automobile[4256] {
+        0 => pub race_organisation: Ps2Ptr<()>,
+        4 => pub dynamics_conductor: Ps2Ptr<()>,
+        16 => pub car_spec: Ps2Ptr<CarSpec>,
+        1420 => pub throttle_pedal: f32,
+        1424 => pub brake1: f32,
+        1432 => pub throttle_actual: f32,
+        1448 => pub meters_driven_in_current_lap: f32,
+        /// from decompiler
+        1456 => pub implicit_current_lap: i16,
+        1536 => pub almost_rpm: f32,
+        1572 => pub gear: u8,
+        1848 => pub rpm: f32,
+        1864 => pub throttle3: f32,
+        1920 => pub throttle4: f32,
+        1924 => pub brake2: f32,
+    }
+}
 
 impl Automobile {
     pub fn progress(&self, track_length: f32) -> OrderedFloat<f32> {
-        let lap: f32 = self.implicit_current_lap.into();
-        (lap + self.meters_driven_in_current_lap / track_length).into()
+        let lap: f32 = self.implicit_current_lap().into();
+        (lap + self.meters_driven_in_current_lap() / track_length).into()
     }
 }
 
-// size unknown, at least 5500 bytes or so
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-pub struct CarSpec {
-    unknown: [u8; 72],
-    pub mass: f32,
-    // more stuff
+game_struct! {
+    /// The size is unknown, at least 5500 bytes or so, but nothing past the mass is read
+    pub struct CarSpec[76] {
+        72 => pub mass: f32,
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-pub struct Entry {
-    // 68 tuning ints, I think each entry is a pair (row id, table id)
-    pub tuning_data: [TuningItem; 34],
-    pub _unk0: [u8; 132],
-    pub timing_data: [i32; 1038],
-    pub _unk1: [u8; 8720],
-    pub engine_sound_path: [u8; 32],
-    pub normal_sound_path: [u8; 32],
-    pub _unk2: [u8; 80],
-    pub car_name_short: Ps2String<128>,
-    pub car_name: Ps2String<192>,
-    pub _unk3: [u8; 52],
+game_struct! {
+    pub struct Entry[13792] {
+        /// 68 tuning ints, I think each entry is a pair (row id, table id)
+        0 => pub tuning_data: [TuningItem; 34],
+        404 => pub timing_data: [i32; 1038],
+        13276 => pub engine_sound_path: [u8; 32],
+        13308 => pub normal_sound_path: [u8; 32],
+        13420 => pub car_name_short: Ps2String<128>,
+        13548 => pub car_name: Ps2String<192>,
+    }
 }
 
-static_assertions::assert_eq_size!([u8; 13792], Entry);
-
 impl Entry {
     pub fn tuning(&self, table: TuningTable) -> Option<i32> {
-        for item in self.tuning_data.iter() {
-            if item.table == table {
+        for item in self.tuning_data().iter() {
+            if item.table() == Some(table) {
                 return Some(item.row_id);
             }
         }
@@ -110,7 +98,14 @@ impl Entry {
 #[derive(Copy, Clone, Debug)]
 pub struct TuningItem {
     pub row_id: i32,
-    pub table: TuningTable,
+    /// kept as read, since any value could be in memory but only some are a `TuningTable`
+    pub table_id: i32,
+}
+
+impl TuningItem {
+    pub fn table(&self) -> Option<TuningTable> {
+        TuningTable::from_id(self.table_id)
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -129,13 +124,65 @@ pub enum TuningTable {
     REAR_TIRE = 26,
 }
 
-type TimeMs = i32;
+impl TuningTable {
+    pub const ALL: [TuningTable; 10] = [
+        TuningTable::GENERIC_CAR,
+        TuningTable::BRAKE,
+        TuningTable::CHASSIS,
+        TuningTable::RACING_MODIFY,
+        TuningTable::DRIVETRAIN,
+        TuningTable::GEARING,
+        TuningTable::ENGINE,
+        TuningTable::RACING_CHIP,
+        TuningTable::FRONT_TIRE,
+        TuningTable::REAR_TIRE,
+    ];
+
+    pub fn from_id(id: i32) -> Option<TuningTable> {
+        TuningTable::ALL.iter().copied().find(|&table| table as i32 == id)
+    }
+}
+
+pub type TimeMs = i32;
+
+/// map from distance through the race to time at which this distance was reached
+pub type Checkpoints = BTreeMap<OrderedFloat<f32>, TimeMs>;
 
 pub struct GameData<M: Ps2Memory> {
     pub ps2: M,
     /// for each car, a map from distance through the race to time at which this distance was reached
-    pub car_checkpoints: [BTreeMap<OrderedFloat<f32>, TimeMs>; MAX_CARS],
+    pub car_checkpoints: [Checkpoints; MAX_CARS],
     pub race_time: TimeMs,
+    pub sector_splits: SectorSplits,
+    /// length of the race, needed to project the finish. Only ever typed into the overlay, since
+    /// it hasn't been found in the game's memory
+    pub race_laps: Option<i16>,
+    /// starting position of each car, counting from 1, if we saw the cars before the start
+    pub grid_positions: [Option<usize>; MAX_CARS],
+    pub positions: PositionTracker,
+    pub pits: [PitTracker; MAX_CARS],
+    /// which car is the player's, from the config
+    pub player_slot: Option<usize>,
+    pub player_detector: PlayerDetector,
+    /// everywhere each sample is sent
+    pub sinks: Vec<Box<dyn RaceSink + Send>>,
+    /// how car names are decoded, if set in the config rather than found from the game
+    pub text_encoding: Option<TextEncoding>,
+    /// display names, classes and colours of cars
+    pub car_database: CarDatabase,
+    /// names, sectors and outlines of tracks
+    pub track_database: TrackDatabase,
+    /// the release of the game, looked for when a race is first loaded and kept for the rest of
+    /// the connection once found
+    pub game_version: Option<(ConnectionStatus, GameVersion)>,
+    /// when the release was last looked for without finding it, to wait before trying again
+    game_version_missed: Option<(ConnectionStatus, Instant)>,
+    /// the checks each car failed last sample, to log only when they change
+    validations: Vec<Validation>,
+    /// length of the track last identified, to log only when it changes
+    identified_track_length: Option<f32>,
+    /// the player's car and the hint last sample, to log only when they change
+    last_player: Option<(usize, PlayerSource, Option<usize>)>,
 }
 
 // offset from EE main memory base to start of NaN block for cars[1]
@@ -144,26 +191,62 @@ const FIRST_NAN_OFFSET_FROM_EE_BASE: usize = 0x01C0EEA4;
 const FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP: usize = 0x01C0F964;
 
 impl GameData<Ps2InProcess> {
-    pub fn in_same_process() -> Self {
-        return GameData {
-            ps2: Ps2InProcess,
-            car_checkpoints: [
-                BTreeMap::new(),
-                BTreeMap::new(),
-                BTreeMap::new(),
-                BTreeMap::new(),
-                BTreeMap::new(),
-                BTreeMap::new(),
-            ],
-            race_time: 0,
-        };
+    pub fn in_same_process(config: &Config) -> Self {
+        GameData::new(Ps2InProcess, config)
+    }
+}
+
+impl GameData<Ps2MemoryImage> {
+    pub fn from_image(image: Ps2MemoryImage, config: &Config) -> Self {
+        GameData::new(image, config)
     }
 }
 
 impl GameData<Ps2SeparateProcess> {
-    pub fn connect(process_handle: ProcessHandle) -> Self {
+    pub fn connect(process_handle: ProcessHandle, config: &Config) -> Self {
+        GameData::new(Ps2SeparateProcess { pcsx2_process_handle: process_handle }, config)
+    }
+}
+
+pub struct RaceState {
+    pub race_time: TimeMs,
+    pub track_length: f32,
+    /// the track in the table closest in length, which is only a guess since nothing else about
+    /// the track is read
+    pub track_guess: Option<TrackInfo>,
+    pub cars: Vec<Automobile>,
+    pub entries: Vec<Entry>,
+    /// short name of each car, decoded from its entry
+    pub names: Vec<String>,
+    /// how each car is shown, looked up from its entry
+    pub labels: Vec<CarLabel>,
+    pub gaps_to_leader: Vec<Option<f32>>,
+    /// in m/s, once a car has crossed the start line
+    pub speeds: Vec<Option<f32>>,
+    pub laps: Vec<LapTimes>,
+    pub sectors: Vec<CarSectors>,
+    /// fastest time for each sector across all cars
+    pub best_sectors: Vec<Option<f32>>,
+    /// empty unless the race length is known
+    pub projections: Vec<Option<Projection>>,
+    pub player_source: PlayerSource,
+    /// a car the pedals suggest is the player's, while `player_slot` isn't set
+    pub player_hint: Option<usize>,
+    pub is_player: Vec<bool>,
+    /// whether each car's values look like they were read from the right place
+    pub validations: Vec<Validation>,
+    /// starting position of each car, counting from 1
+    pub grid_positions: Vec<Option<usize>>,
+    /// current race position of each car, counting from 1
+    pub positions: Vec<usize>,
+    pub pits: Vec<PitSummary>,
+}
+
+impl<M: Ps2Memory> GameData<M> {
+    /// Reads the game from any memory, such as a recording of another
+    pub fn new(ps2: M, config: &Config) -> Self {
         return GameData {
-            ps2: Ps2SeparateProcess { pcsx2_process_handle: process_handle },
+            ps2,
             car_checkpoints: [
                 BTreeMap::new(),
                 BTreeMap::new(),
@@ -173,21 +256,54 @@ impl GameData<Ps2SeparateProcess> {
                 BTreeMap::new(),
             ],
             race_time: 0,
+            sector_splits: SectorSplits::from_config(&config.sectors),
+            race_laps: None,
+            grid_positions: [None; MAX_CARS],
+            positions: PositionTracker::default(),
+            pits: Default::default(),
+            player_slot: config.player_slot,
+            player_detector: PlayerDetector::default(),
+            sinks: sinks::from_config(config),
+            text_encoding: config.text_encoding,
+            car_database: CarDatabase::new(&config.cars),
+            track_database: TrackDatabase::new(&config.tracks),
+            game_version: None,
+            game_version_missed: None,
+            validations: vec![Validation::default(); MAX_CARS],
+            identified_track_length: None,
+            last_player: None,
         };
     }
-}
 
-pub struct RaceState {
-    pub track_length: f32,
-    pub cars: Vec<Automobile>,
-    pub entries: Vec<Entry>,
-    pub gaps_to_leader: Vec<Option<f32>>,
-}
+    /// Forgets everything recorded about the previous race
+    fn start_new_race(&mut self) {
+        for i in 0..MAX_CARS {
+            self.car_checkpoints[i].clear()
+        }
+        self.grid_positions = [None; MAX_CARS];
+        self.positions.reset();
+        for pits in self.pits.iter_mut() {
+            pits.reset();
+        }
+        self.player_detector.reset();
+    }
+
+    /// Records the running order as the grid while every car is still behind the start line
+    fn update_grid(&mut self, cars: &[Automobile], track_length: f32) {
+        if cars.iter().any(|c| c.progress(track_length) >= 1f32.into()) {
+            return;
+        }
+        let mut order: Vec<_> = (0..MAX_CARS).collect();
+        order.sort_by_key(|&i| Reverse(cars[i].progress(track_length)));
+        for (position, &i) in order.iter().enumerate() {
+            self.grid_positions[i] = Some(position + 1);
+        }
+    }
 
-impl<M: Ps2Memory> GameData<M> {
     pub fn sample_race(&mut self) -> Result<RaceState> {
         log::trace!("finding addresses");
-        let addresses = Addresses::find(&self.ps2).context("no addresses")?;
+        let addresses = Addresses::find(&self.ps2)?.context("no race loaded")?;
+        self.find_game_version();
         // TODO: handle less than 6 cars e.g. special events
         // TODO: handle practice mode. not sure what goes wrong.
         log::trace!("getting cars");
@@ -196,28 +312,196 @@ impl<M: Ps2Memory> GameData<M> {
         let entries = addresses.entries.get(&self.ps2)?.to_vec();
         log::trace!("getting track length");
         let track_length = addresses.track_length.get(&self.ps2)?;
+        let track_guess = self.guess_track(track_length);
+        let names: Vec<_> =
+            entries.iter().map(|e| e.car_name_short().decode(self.encoding())).collect();
+        let labels =
+            entries.iter().zip(&names).map(|(e, n)| self.car_database.label(e, n)).collect();
+        log::trace!("validating cars");
+        let validations: Vec<_> = (0..MAX_CARS)
+            .map(|i| validation::validate_car(&self.ps2, &cars[i], &entries[i], track_length))
+            .collect();
+        self.log_validation_changes(&validations);
         {
             log::trace!("getting race time");
             let new_race_time = addresses.race_time.get(&self.ps2)?;
             if new_race_time < self.race_time {
-                for i in 0..MAX_CARS {
-                    self.car_checkpoints[i].clear()
-                }
+                self.start_new_race();
             }
             self.race_time = new_race_time;
         }
-        for i in 0..MAX_CARS {
+        // cars that look misread still get a position, flagged in every output, but aren't timed
+        let valid: Vec<_> = validations.iter().map(Validation::is_valid).collect();
+        self.update_grid(&cars, track_length);
+        self.positions.update(&cars, &valid, track_length, self.race_time);
+        for i in (0..MAX_CARS).filter(|&i| valid[i]) {
+            self.pits[i].update(&cars[i], track_length, self.race_time, self.race_laps);
+        }
+        self.player_detector.update(&cars);
+        let (player, player_source) = player::player(self.player_slot);
+        let player_hint = match player_source {
+            PlayerSource::Config => None,
+            PlayerSource::Assumed => self.player_detector.hint(),
+        };
+        let last_player = Some((player, player_source, player_hint));
+        if std::mem::replace(&mut self.last_player, last_player) != last_player {
+            log::info!("player's car is slot {}, {}", player, player_source);
+            if let Some(hint) = player_hint {
+                log::info!(
+                    "car {} looks driven from a pad, set player_slot = {} if it's yours",
+                    hint,
+                    hint
+                );
+            }
+        }
+        for i in (0..MAX_CARS).filter(|&i| valid[i]) {
             let progress = cars[i].progress(track_length);
             if progress >= 1f32.into() {
                 self.car_checkpoints[i].insert(progress, self.race_time);
             }
         }
 
-        let gaps_to_leader = (0..MAX_CARS)
+        let gaps_to_leader: Vec<_> = (0..MAX_CARS)
             .map(|i| self.calculate_gap_to_leader_ms(i, &cars, track_length, self.race_time as f32))
             .collect();
+        let laps = (0..MAX_CARS)
+            .map(|i| {
+                LapTimes::of(
+                    &self.car_checkpoints[i],
+                    cars[i].progress(track_length),
+                    self.race_time,
+                )
+            })
+            .collect();
+        let speeds = self
+            .car_checkpoints
+            .iter()
+            .map(|checkpoints| speed(checkpoints, track_length))
+            .collect();
 
-        Ok(RaceState { track_length, cars, entries, gaps_to_leader })
+        let suggested = track_guess.as_ref().map_or(&[][..], |track| &track.sectors);
+        let boundaries = self.sector_splits.boundaries(track_length, suggested);
+        let sectors: Vec<_> = self
+            .car_checkpoints
+            .iter()
+            .map(|checkpoints| CarSectors::from_checkpoints(checkpoints, &boundaries))
+            .collect();
+        let best_sectors = sectors::overall_best(&sectors);
+
+        let projections = match self.race_laps {
+            Some(race_laps) => {
+                let progress: Vec<_> =
+                    cars.iter().map(|c| c.progress(track_length).into_inner()).collect();
+                projection::project(
+                    race_laps,
+                    self.race_time,
+                    &progress,
+                    &self.car_checkpoints,
+                    &gaps_to_leader,
+                )
+            }
+            None => Vec::new(),
+        };
+
+        let race = RaceState {
+            race_time: self.race_time,
+            track_length,
+            track_guess,
+            cars,
+            entries,
+            names,
+            labels,
+            gaps_to_leader,
+            speeds,
+            laps,
+            sectors,
+            best_sectors,
+            projections,
+            player_source,
+            player_hint,
+            is_player: (0..MAX_CARS).map(|i| i == player).collect(),
+            validations,
+            grid_positions: self.grid_positions.to_vec(),
+            positions: self.positions.positions(),
+            pits: (0..MAX_CARS)
+                .map(|i| PitSummary::of(&self.pits[i], &self.car_checkpoints[i]))
+                .collect(),
+        };
+
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.publish(&race) {
+                log::warn!("error publishing sample: {:?}", e);
+            }
+        }
+
+        Ok(race)
+    }
+
+    fn find_game_version(&mut self) {
+        let status = self.ps2.connection_status();
+        if matches!(&self.game_version, Some((found, _)) if *found == status) {
+            return;
+        }
+        let missed_before = match &self.game_version_missed {
+            Some((missed, at)) if *missed == status => {
+                if at.elapsed() < GAME_VERSION_RETRY {
+                    return;
+                }
+                true
+            }
+            _ => false,
+        };
+        match GameVersion::detect(&self.ps2) {
+            Ok(Some(version)) => {
+                log::info!("game is {}", version);
+                self.game_version = Some((status, version));
+                self.game_version_missed = None;
+            }
+            missed => {
+                // only warned about once per connection, as it is looked for again and again
+                if !missed_before {
+                    match missed {
+                        Err(e) => log::warn!("couldn't look for the game's release: {}", e),
+                        _ => log::warn!("couldn't tell which release of the game is running"),
+                    }
+                }
+                self.game_version_missed = Some((status, Instant::now()));
+            }
+        }
+    }
+
+    fn guess_track(&mut self, track_length: f32) -> Option<TrackInfo> {
+        let track = self.track_database.identify(track_length).cloned();
+        if self.identified_track_length != Some(track_length) {
+            match &track {
+                Some(track) => {
+                    log::info!("going by its length, the track is {}", track.full_name())
+                }
+                None => log::info!("no track in the table is {:.0}m long", track_length),
+            }
+            self.identified_track_length = Some(track_length);
+        }
+        track
+    }
+
+    /// How car names are encoded, from the config or else the release of the game
+    pub fn encoding(&self) -> TextEncoding {
+        let detected = self.game_version.as_ref().map(|(_, version)| version.text_encoding());
+        self.text_encoding.or(detected).unwrap_or(TextEncoding::Windows1252)
+    }
+
+    fn log_validation_changes(&mut self, validations: &[Validation]) {
+        for (i, (last, new)) in self.validations.iter().zip(validations).enumerate() {
+            if last == new {
+                continue;
+            }
+            if new.is_valid() {
+                log::info!("car {} passes its checks again", i);
+            } else {
+                log::warn!("car {} failed checks: {}", i, new.failed.join("; "));
+            }
+        }
+        self.validations = validations.to_vec();
     }
 
     fn calculate_gap_to_leader_ms(
@@ -237,16 +521,9 @@ impl<M: Ps2Memory> GameData<M> {
             if i == car {
                 continue;
             }
-            let min_greater = self.car_checkpoints[i].range(progress_to_find..).next();
-            let max_less = self.car_checkpoints[i].range(..progress_to_find).next_back();
-
-            if let (Some(min_greater), Some(max_less)) = (min_greater, max_less) {
-                // linearly interpolate
-                let alpha: OrderedFloat<f32> =
-                    (progress_to_find - *max_less.0) / (*min_greater.0 - *max_less.0);
-                let interpolated_time: f32 =
-                    *max_less.1 as f32 + alpha.into_inner() * (*min_greater.1 - *max_less.1) as f32;
-
+            if let Some(interpolated_time) =
+                time_at_progress(&self.car_checkpoints[i], progress_to_find.into_inner())
+            {
                 if let Some(t) = leader_time {
                     if t > interpolated_time {
                         leader_time = Some(interpolated_time)
@@ -260,49 +537,132 @@ impl<M: Ps2Memory> GameData<M> {
     }
 }
 
-struct Addresses {
-    cars: Ps2Ptr<[Automobile; MAX_CARS]>,
-    entries: Ps2Ptr<[Entry; MAX_CARS]>,
-    race_time: Ps2Ptr<TimeMs>,
-    track_length: Ps2PtrChain<f32>,
+/// Formats a race time as minutes and seconds, e.g. `1:23.4`
+pub fn format_race_time(ms: TimeMs) -> String {
+    let seconds = ms as f32 / 1000f32;
+    format!("{}:{:04.1}", (seconds / 60.0).floor(), seconds % 60.0)
+}
+
+/// The time at which a car reached the given progress, interpolated between its checkpoints
+pub fn time_at_progress(checkpoints: &Checkpoints, progress: f32) -> Option<f32> {
+    let progress_to_find: OrderedFloat<f32> = progress.into();
+    let min_greater = checkpoints.range(progress_to_find..).next();
+    let max_less = checkpoints.range(..progress_to_find).next_back();
+
+    if let (Some(min_greater), Some(max_less)) = (min_greater, max_less) {
+        // linearly interpolate
+        let alpha: OrderedFloat<f32> =
+            (progress_to_find - *max_less.0) / (*min_greater.0 - *max_less.0);
+        Some(*max_less.1 as f32 + alpha.into_inner() * (*min_greater.1 - *max_less.1) as f32)
+    } else {
+        None
+    }
+}
+
+/// Lap times of a car in ms
+#[derive(Clone, Debug)]
+pub struct LapTimes {
+    /// time so far in the lap the car is on
+    pub current: Option<f32>,
+    pub last: Option<f32>,
+    pub best: Option<f32>,
+}
+
+impl LapTimes {
+    pub fn of(checkpoints: &Checkpoints, progress: OrderedFloat<f32>, race_time: TimeMs) -> Self {
+        let completed = lap_times(checkpoints);
+        let lap_start = time_at_progress(checkpoints, progress.floor());
+        LapTimes {
+            current: lap_start.map(|start| race_time as f32 - start),
+            last: completed.last().map(|&(_, time)| time),
+            best: completed.iter().map(|&(_, time)| time).fold(None, |best: Option<f32>, time| {
+                Some(best.map_or(time, |best| best.min(time)))
+            }),
+        }
+    }
+}
+
+/// how far back to look when working out speed, to smooth out sampling jitter
+const SPEED_WINDOW_MS: TimeMs = 200;
+
+/// Recent speed of a car in m/s, worked out from its checkpoints
+pub fn speed(checkpoints: &Checkpoints, track_length: f32) -> Option<f32> {
+    let mut recent = checkpoints.iter().rev();
+    let (latest_progress, &latest_time) = recent.next()?;
+    let (earlier_progress, &earlier_time) =
+        recent.find(|(_, &time)| latest_time - time >= SPEED_WINDOW_MS)?;
+    let metres = (latest_progress.into_inner() - earlier_progress.into_inner()) * track_length;
+    Some(metres / ((latest_time - earlier_time) as f32 / 1000.0))
+}
+
+/// Completed lap times in ms as (lap, time) pairs, oldest first
+pub fn lap_times(checkpoints: &Checkpoints) -> Vec<(i32, f32)> {
+    let (first_lap, last_lap) = match (checkpoints.keys().next(), checkpoints.keys().next_back()) {
+        (Some(first), Some(last)) => {
+            (first.into_inner().floor() as i32, last.into_inner().floor() as i32)
+        }
+        _ => return Vec::new(),
+    };
+    (first_lap..last_lap)
+        .filter_map(|lap| {
+            let start = time_at_progress(checkpoints, lap as f32)?;
+            let end = time_at_progress(checkpoints, (lap + 1) as f32)?;
+            Some((lap, end - start))
+        })
+        .collect()
+}
+
+/// Where the race data lives in EE memory
+pub struct Addresses {
+    pub cars: Ps2Ptr<[Automobile; MAX_CARS]>,
+    pub entries: Ps2Ptr<[Entry; MAX_CARS]>,
+    pub race_time: Ps2Ptr<TimeMs>,
+    pub track_length: Ps2PtrChain<f32>,
 }
 
 impl Addresses {
-    fn find(ps2_memory: &impl Ps2Memory) -> Option<Addresses> {
-        if f32::is_nan(Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE as u32).get(ps2_memory).unwrap()) {
+    /// The addresses for whichever kind of race is loaded, or None if there isn't a race
+    fn find(ps2_memory: &impl Ps2Memory) -> Ps2Result<Option<Addresses>> {
+        let is_nan = |offset: usize| -> Ps2Result<bool> {
+            Ok(Ps2Ptr::<f32>::new(offset as u32).get(ps2_memory)?.is_nan())
+        };
+        Ok(if is_nan(FIRST_NAN_OFFSET_FROM_EE_BASE)? {
             log::trace!("trying normal addresses");
-            Some(Addresses {
-                cars: Ps2Ptr::new(
-                    (
-                        FIRST_NAN_OFFSET_FROM_EE_BASE
-                    - BEFORE_NANS  // go to start of Automobile struct
-                    - size_of::<Automobile>()
-                        //  that was entry 1, go to entry 0
-                    ) as u32,
-                ),
-                entries: Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE as u32 - 0x2E0A4),
-                race_time: Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE as u32 - 0xA4A0),
-                track_length: Ps2PtrChain::new(vec![0x01BF52FC, 404, 20]),
-            })
-        } else if f32::is_nan(
-            Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP as u32).get(ps2_memory).unwrap(),
-        ) {
+            Some(Addresses::normal())
+        } else if is_nan(FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP)? {
             log::trace!("trying champ addresses");
-            Some(Addresses {
-                cars: Ps2Ptr::new(
-                    (
-                        FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP
-                    - BEFORE_NANS  // go to start of Automobile struct
-                    - size_of::<Automobile>()
-                        //  that was entry 1, go to entry 0
-                    ) as u32,
-                ),
-                entries: Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP as u32 - 0x2E0A4),
-                race_time: Ps2Ptr::new(FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP as u32 - 0xA4A0),
-                track_length: Ps2PtrChain::new(vec![0x01BF5DBC, 404, 20]),
-            })
+            Some(Addresses::championship())
         } else {
             None
+        })
+    }
+
+    pub fn normal() -> Addresses {
+        Addresses::relative_to_nans(FIRST_NAN_OFFSET_FROM_EE_BASE, 0x01BF52FC)
+    }
+
+    pub fn championship() -> Addresses {
+        Addresses::relative_to_nans(FIRST_NAN_OFFSET_FROM_EE_BASE_CHAMP, 0x01BF5DBC)
+    }
+
+    fn relative_to_nans(first_nan_offset: usize, track_length_base: u32) -> Addresses {
+        Addresses {
+            cars: Ps2Ptr::new(
+                (
+                    first_nan_offset
+                    - BEFORE_NANS  // go to start of Automobile struct
+                    - size_of::<Automobile>()
+                    //  that was entry 1, go to entry 0
+                ) as u32,
+            ),
+            entries: Ps2Ptr::new(first_nan_offset as u32 - 0x2E0A4),
+            race_time: Ps2Ptr::new(first_nan_offset as u32 - 0xA4A0),
+            track_length: Ps2PtrChain::new(vec![track_length_base, 404, 20]),
         }
     }
+
+    /// The f32 that is always NaN while a race is loaded, used to tell which addresses apply
+    pub fn nan_marker(&self) -> Ps2Ptr<f32> {
+        Ps2Ptr::new(self.cars.address() + (size_of::<Automobile>() + BEFORE_NANS) as u32)
+    }
 }
@@ -0,0 +1,114 @@
+// Game structs are only partly understood, so rather than padding a `#[repr(C)]` struct out with
+// unknown byte arrays, `game_struct!` declares just the fields we know at their offsets over the
+// struct's raw bytes.
+
+/// Declares a struct of the game's as its size in bytes and the fields known in it, in order of
+/// offset:
+///
+/// ```ignore
+/// game_struct! {
+///     pub struct CarSpec[76] {
+///         72 => pub mass: f32,
+///     }
+/// }
+/// ```
+///
+/// Each field gets a getter, e.g. `car_spec.mass()`, and a setter, `set_mass`. A `Ps2Ptr` or
+/// `Ps2PtrChain` to the struct gets a method per field giving a pointer to just that field, so
+/// one field can be read without the rest. It fails to compile if fields overlap, are out of
+/// order or run past the end. Field types have to be plain data that any bytes are valid for.
+#[macro_export]
+macro_rules! game_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident[$size:expr] {
+            $(
+                $(#[$field_meta:meta])*
+                $offset:literal => $field_vis:vis $field:ident: $type:ty,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy)]
+        $vis struct $name([u8; $size]);
+
+        static_assertions::const_assert!($crate::game_struct::fields_fit(
+            &[$(($offset, std::mem::size_of::<$type>())),*],
+            $size
+        ));
+
+        #[allow(dead_code)]
+        impl $name {
+            pub const SIZE: usize = $size;
+
+            pub fn zeroed() -> Self {
+                $name([0; $size])
+            }
+
+            /// The struct's bytes, including those we don't know the meaning of
+            pub fn bytes(&self) -> &[u8; $size] {
+                &self.0
+            }
+
+            $(
+                $(#[$field_meta])*
+                $field_vis fn $field(&self) -> $type {
+                    unsafe { (self.0.as_ptr().add($offset) as *const $type).read_unaligned() }
+                }
+
+                paste::paste! {
+                    $field_vis fn [<set_ $field>](&mut self, value: $type) {
+                        let field = unsafe { self.0.as_mut_ptr().add($offset) as *mut $type };
+                        unsafe { field.write_unaligned(value) };
+                    }
+                }
+            )*
+        }
+
+        #[allow(dead_code)]
+        impl $crate::ps2_types::Ps2Ptr<$name> {
+            $(
+                $field_vis fn $field(&self) -> $crate::ps2_types::Ps2Ptr<$type> {
+                    self.byte_offset($offset)
+                }
+            )*
+        }
+
+        #[allow(dead_code)]
+        impl $crate::ps2_types::Ps2PtrChain<$name> {
+            $(
+                $field_vis fn $field(&self) -> $crate::ps2_types::Ps2PtrChain<$type> {
+                    let mut offsets = self.offsets().to_vec();
+                    if let Some(last) = offsets.last_mut() {
+                        *last = last.wrapping_add($offset);
+                    }
+                    $crate::ps2_types::Ps2PtrChain::new(offsets)
+                }
+            )*
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.debug_struct(stringify!($name))
+                    $(.field(stringify!($field), &self.$field()))*
+                    .finish()
+            }
+        }
+    };
+}
+
+/// Whether fields given as offset and size are in order, don't overlap and fit in `size` bytes
+pub const fn fields_fit(fields: &[(usize, usize)], size: usize) -> bool {
+    let mut end = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        let (offset, len) = fields[i];
+        if offset < end {
+            return false;
+        }
+        end = offset + len;
+        i += 1;
+    }
+    end <= size
+}
@@ -0,0 +1,106 @@
+use crate::ps2_types::{Ps2Memory, Ps2Region, Ps2Result, TextEncoding};
+use std::fmt;
+
+/// what the BIOS boots the disc's executable from, e.g. `cdrom0:\SCUS_973.28;1`
+const BOOT_PATH_PREFIX: &[u8] = b"cdrom0:\\";
+
+/// how much of EE RAM is searched at a time for the boot path
+const CHUNK_SIZE: usize = 0x10000;
+/// the boot path and the executable name after it
+const BOOT_PATH_LEN: usize = BOOT_PATH_PREFIX.len() + 11;
+
+/// Releases of GT4 by the serial on their disc
+const KNOWN_RELEASES: [(&str, &str); 3] = [
+    ("SCUS-97328", "Gran Turismo 4 (NTSC-U)"),
+    ("SCES-51719", "Gran Turismo 4 (PAL)"),
+    ("SCPS-17001", "Gran Turismo 4 (NTSC-J)"),
+];
+
+/// The game running in an emulator, by the serial of its disc
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameVersion {
+    /// e.g. SCUS-97328
+    pub serial: String,
+    pub name: Option<&'static str>,
+}
+
+impl GameVersion {
+    /// Looks for the path the game's executable was booted from, which stays in memory
+    pub fn detect(memory: &impl Ps2Memory) -> Ps2Result<Option<GameVersion>> {
+        // chunks overlap by the length of the path, so one can't be split between two
+        let step = CHUNK_SIZE - BOOT_PATH_LEN;
+        let last = Ps2Region::Ee.size() - CHUNK_SIZE;
+        for address in (0..Ps2Region::Ee.size()).step_by(step).map(|address| address.min(last)) {
+            let chunk = memory.read::<[u8; CHUNK_SIZE]>(address as u32)?;
+            let found = chunk
+                .windows(BOOT_PATH_LEN)
+                .filter(|path| path.starts_with(BOOT_PATH_PREFIX))
+                .find_map(|path| GameVersion::from_executable(&path[BOOT_PATH_PREFIX.len()..]));
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// The release with an executable, e.g. SCUS_973.28
+    fn from_executable(executable: &[u8]) -> Option<GameVersion> {
+        let valid = executable.iter().enumerate().all(|(i, &c)| match i {
+            0..=3 => c.is_ascii_uppercase(),
+            4 => c == b'_',
+            8 => c == b'.',
+            _ => c.is_ascii_digit(),
+        });
+        if !valid {
+            return None;
+        }
+        let executable = String::from_utf8_lossy(executable);
+        let serial = format!("{}-{}{}", &executable[0..4], &executable[5..8], &executable[9..11]);
+        let name = KNOWN_RELEASES.iter().find(|(known, _)| *known == serial).map(|(_, name)| *name);
+        Some(GameVersion { serial, name })
+    }
+
+    /// Japanese releases, whose serials start SCPS, SLPS or SLPM, use Shift-JIS
+    pub fn text_encoding(&self) -> TextEncoding {
+        if ["SCPS", "SLPS", "SLPM"].iter().any(|prefix| self.serial.starts_with(prefix)) {
+            TextEncoding::ShiftJis
+        } else {
+            TextEncoding::Windows1252
+        }
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{} {}", self.serial, name),
+            None => write!(f, "{} (not a known release of GT4)", self.serial),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ps2_types::Ps2MemoryImage;
+
+    #[test]
+    fn detects_the_release_after_the_system_cnf_path() {
+        // as the BIOS leaves them: the path it read SYSTEM.CNF from, then SYSTEM.CNF itself, with
+        // the executable's path across the boundary between two chunks
+        let mut memory = Ps2MemoryImage::blank();
+        memory.write_bytes(0x1000, b"cdrom0:\\SYSTEM.CNF;1\0").unwrap();
+        let cnf = b"BOOT2 = cdrom0:\\SCUS_973.28;1\r\nVER = 1.00\r\nVMODE = NTSC\r\n";
+        memory.write_bytes(CHUNK_SIZE as u32 - 20, cnf).unwrap();
+
+        let version = GameVersion::detect(&memory).unwrap().unwrap();
+        assert_eq!(version.serial, "SCUS-97328");
+        assert_eq!(version.name, Some("Gran Turismo 4 (NTSC-U)"));
+        assert_eq!(version.text_encoding(), TextEncoding::Windows1252);
+    }
+
+    #[test]
+    fn finds_nothing_before_a_disc_is_booted() {
+        assert_eq!(GameVersion::detect(&Ps2MemoryImage::blank()).unwrap(), None);
+    }
+}
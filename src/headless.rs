@@ -0,0 +1,131 @@
+use crate::{
+    game_data::{format_race_time, GameData, RaceState},
+    player::PlayerSource,
+    ps2_types::{ConnectionStatus, Ps2Memory},
+};
+use anyhow::Result;
+use crossterm::{
+    cursor::MoveTo,
+    queue,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal::{Clear, ClearType},
+};
+use std::{
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// how often the game is sampled, independent of how often the tower is redrawn, so gaps are
+/// as accurate as in the overlay
+const SAMPLE_HZ: f32 = 60.0;
+
+/// Samples each game and redraws their timing towers in the terminal until stopped.
+/// `before_sample` runs ahead of each sample, to move a fake race or a replay along.
+pub fn run<M: Ps2Memory>(
+    games: &mut [GameData<M>],
+    refresh_hz: f32,
+    mut before_sample: impl FnMut(&mut GameData<M>) -> Result<()>,
+) -> Result<()> {
+    let sample_interval = Duration::from_secs_f32(1.0 / SAMPLE_HZ);
+    let refresh_interval = Duration::from_secs_f32(1.0 / refresh_hz);
+    let mut last_refresh: Option<Instant> = None;
+    let mut stdout = io::stdout();
+    loop {
+        let started = Instant::now();
+        let mut races = Vec::with_capacity(games.len());
+        for game_data in games.iter_mut() {
+            before_sample(game_data)?;
+            races.push(game_data.sample_race());
+        }
+        if last_refresh.map_or(true, |last| last.elapsed() >= refresh_interval) {
+            last_refresh = Some(started);
+            queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+            for (game_data, race) in games.iter().zip(&races) {
+                match game_data.ps2.connection_status() {
+                    ConnectionStatus::Local => {}
+                    ConnectionStatus::Waiting => queue!(stdout, Print("Waiting for PCSX2\n"))?,
+                    ConnectionStatus::Attached { pid, name } => {
+                        queue!(stdout, Print(format!("Reading {} ({})\n", name, pid)))?
+                    }
+                    ConnectionStatus::Lost { pid } => {
+                        queue!(stdout, Print(format!("PCSX2 {} has exited\n", pid)))?
+                    }
+                }
+                match race {
+                    Ok(race) => render_tower(&mut stdout, race)?,
+                    Err(e) => queue!(stdout, Print(format!("waiting for a race: {:#}\n", e)))?,
+                }
+                queue!(stdout, Print("\n"))?;
+            }
+            stdout.flush()?;
+        }
+        if let Some(remaining) = sample_interval.checked_sub(started.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+/// position, name, gap to the leader, lap, gear and rpm of each car in race order
+fn render_tower(out: &mut impl Write, r: &RaceState) -> Result<()> {
+    let track = match &r.track_guess {
+        Some(track) => format!("{} (guessed from its length)", track.full_name()),
+        None => format!("{:.0}m track", r.track_length),
+    };
+    queue!(out, Print(format!("{}  Race time {}\n\n", track, format_race_time(r.race_time))))?;
+    queue!(
+        out,
+        Print(format!(
+            "{:>3}  {:<20} {:>8} {:>4} {:>4} {:>6}\n",
+            "Pos", "Name", "Gap", "Lap", "Gear", "RPM"
+        ))
+    )?;
+    let mut order: Vec<_> = (0..r.cars.len()).collect();
+    order.sort_by_key(|&i| r.positions[i]);
+    for i in order {
+        let car = &r.cars[i];
+        let name = &r.labels[i].name;
+        let gap = match r.gaps_to_leader[i] {
+            _ if r.positions[i] == 1 => "Leader".to_owned(),
+            Some(gap) => format!("+{:.3}", gap / 1000.0),
+            None => "-".to_owned(),
+        };
+        let pit = if r.pits[i].in_pit { "  PIT" } else { "" };
+        let line = format!(
+            "{:>3}  {:<20} {:>8} {:>4} {:>4} {:>6.0}{}\n",
+            r.positions[i],
+            name,
+            gap,
+            car.implicit_current_lap(),
+            car.gear(),
+            car.rpm(),
+            pit
+        );
+        let validation = &r.validations[i];
+        if !validation.is_valid() {
+            let line = format!(
+                "{:>3}  {:<20} failed checks: {}\n",
+                r.positions[i],
+                name,
+                validation.failed.join("; ")
+            );
+            queue!(out, SetForegroundColor(Color::DarkGrey), Print(line), ResetColor)?;
+        } else if r.is_player[i] {
+            queue!(out, SetForegroundColor(Color::Yellow), Print(line), ResetColor)?;
+        } else {
+            queue!(out, Print(line))?;
+        }
+    }
+    if r.player_source == PlayerSource::Assumed {
+        queue!(
+            out,
+            Print("\nplayer_slot isn't set, so the first car is taken to be the player's\n")
+        )?;
+        if let Some(hint) = r.player_hint {
+            let hint =
+                format!("car {} looks driven from a pad, set player_slot = {}\n", hint, hint);
+            queue!(out, Print(hint))?;
+        }
+    }
+    Ok(())
+}
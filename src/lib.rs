@@ -1,3 +1,4 @@
+use config::Config;
 use game_data::GameData;
 use hudhook::{apply_hook, cleanup_hooks, RenderContext, RenderLoop};
 use log::{LevelFilter, Log, Metadata, Record};
@@ -12,20 +13,44 @@ use std::{
     ptr::null_mut,
     thread,
 };
+use ui::UiOptions;
 use winapi::um::{
     consoleapi::AllocConsole,
     winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH},
 };
 
+mod cars;
+mod cli;
+mod config;
+mod connection;
+mod fake_race;
 mod game_data;
+mod game_struct;
+mod game_version;
+mod headless;
+mod motec;
+mod obs;
+mod pits;
+mod player;
+mod positions;
 mod processes;
+mod projection;
 mod ps2_types;
+mod recording;
 mod scan_memory;
+mod sectors;
+mod server;
+mod sinks;
+mod telemetry;
+mod tracks;
+mod udp;
 mod ui;
+mod validation;
 mod window;
 
 pub struct Gt4TimingRenderLoop<M: Ps2Memory> {
     game_data: GameData<M>,
+    ui_options: UiOptions,
 }
 
 impl<M: Ps2Memory> RenderLoop for Gt4TimingRenderLoop<M> {
@@ -33,7 +58,14 @@ impl<M: Ps2Memory> RenderLoop for Gt4TimingRenderLoop<M> {
         // I have no clue if this really is unwind safe, but this function is called by native code, and exposing it to rust panics cannot possibly be better
         if let Err(e) = catch_unwind(AssertUnwindSafe(|| {
             let scale = ctx.display_size[1] / 480.0;
-            ui::render_ui(ctx.frame, [450., 300.], &mut self.game_data, true, scale)
+            ui::render_ui(
+                ctx.frame,
+                [450., 300.],
+                &mut self.game_data,
+                &mut self.ui_options,
+                true,
+                scale,
+            )
         })) {
             log::error!("{:?}", e);
         }
@@ -85,9 +117,15 @@ pub extern "stdcall" fn DllMain(
                 ])
                 .unwrap_or_else(|e| println!("{}", e));
 
+                let config = Config::load_default().unwrap_or_else(|e| {
+                    log::error!("{:?}", e);
+                    Config::default()
+                });
+
                 log::info!("Started thread, enabling hook");
                 match apply_hook(Box::new(Gt4TimingRenderLoop {
-                    game_data: GameData::in_same_process(),
+                    game_data: GameData::in_same_process(&config),
+                    ui_options: UiOptions::default(),
                 })) {
                     Ok(_) => log::info!("Hook enabled"),
                     Err(e) => log::error!("Hook errored: {:?}", e),
@@ -1,41 +1,262 @@
-use game_data::GameData;
+use anyhow::{bail, Result};
+use cli::{Args, Command, MemoryArgs, ProcessArgs};
+use config::Config;
+use connection::Pcsx2Connection;
+use game_data::{GameData, TimeMs};
+use game_version::GameVersion;
 use log::LevelFilter;
-use process_memory::{Architecture, Pid, ProcessHandleExt, TryIntoProcessHandle};
+use process_memory::ProcessHandle;
+use processes::{Pcsx2Process, Target};
+use ps2_types::{Ps2Address, Ps2Memory, Ps2MemoryImage, Ps2SeparateProcess};
+use recording::{Recorder, Replay};
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode, ThreadLogMode};
-use ui::{init_ui, render_ui};
+use std::{slice, time::Instant};
+use structopt::StructOpt;
+use ui::{init_ui, render_ui, UiOptions};
 use window::App;
 
+mod cars;
+mod cli;
+mod config;
+mod connection;
+mod fake_race;
 mod game_data;
+mod game_struct;
+mod game_version;
+mod headless;
+mod motec;
+mod obs;
+mod pits;
+mod player;
+mod positions;
 mod processes;
+mod projection;
 mod ps2_types;
+mod recording;
 mod scan_memory;
+mod sectors;
+mod server;
+mod sinks;
+mod telemetry;
+mod tracks;
+mod udp;
 mod ui;
+mod validation;
 mod window;
 
 pub struct Locations {
     pub auto_nan_offsets: Vec<usize>,
 }
 
-fn main() {
+fn main() -> Result<()> {
+    let args = Args::from_args();
+
     let log_config = ConfigBuilder::new()
         .set_thread_level(LevelFilter::Error)
         .set_thread_mode(ThreadLogMode::Both)
         .build();
-    TermLogger::init(
-        LevelFilter::Debug,
-        log_config.clone(),
-        TerminalMode::Mixed,
-        ColorChoice::Auto,
-    )
-    .unwrap();
-
-    let pid = processes::get_pcsx2_process_id();
-    let handle = (pid as Pid).try_into_process_handle().unwrap().set_arch(Architecture::Arch32Bit);
-
-    let mut game_data = GameData::connect(handle);
-
-    let window_size = [400.0, 300.0];
-    let mut app = App::init("GT4 timing", window_size);
-    init_ui(&mut app.imgui, app.dpi_factor);
-    app.main_loop(move |ui| render_ui(ui, window_size, &mut game_data, false, 1.0), || {});
+    TermLogger::init(args.log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
+
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    // the overlay window is the default
+    let command = args.command.unwrap_or_else(|| Command::from_iter(&["timing", "live"]));
+    match command {
+        Command::Live { memory, window, sinks } => {
+            sinks.apply(&mut config);
+            open_memory(&memory, &config, View::Window([window.width, window.height]))
+        }
+        Command::Headless { memory, refresh, sinks } => {
+            sinks.apply(&mut config);
+            open_memory(&memory, &config, View::Terminal(refresh.refresh_hz))
+        }
+        Command::Record { output, process, refresh, sinks } => {
+            sinks.apply(&mut config);
+            let names = process.names.names();
+            let target = processes::resolve_target(process.target()?, &names)?;
+            let connection = Pcsx2Connection::new(target, names);
+            let game_data = GameData::new(Recorder::create(connection, &output)?, &config);
+            run(game_data, View::Terminal(refresh.refresh_hz), |game_data| {
+                game_data.ps2.memory_mut().poll();
+                game_data.ps2.finish_frame()
+            })
+        }
+        Command::Replay { recording, headless, window, refresh, sinks } => {
+            sinks.apply(&mut config);
+            let mut replay = Replay::load(&recording)?;
+            let view = if headless {
+                View::Terminal(refresh.refresh_hz)
+            } else {
+                View::Window([window.width, window.height])
+            };
+            let game_data = GameData::from_image(Ps2MemoryImage::blank(), &config);
+            run(game_data, view, move |game_data| replay.apply_next_frame(&mut game_data.ps2))
+        }
+        Command::Scan { process, needle } => {
+            let (found, handle) = attach(&process)?;
+            let bytes = needle.bytes()?;
+            log::info!("searching process {} for {:02x?}", found.pid, bytes);
+            for host_address in scan_memory::find_all_offsets(&bytes, handle) {
+                match Ps2Address::of_host(host_address) {
+                    Some(address) => println!("{:08x} ({})", host_address, address),
+                    None => println!("{:08x}", host_address),
+                }
+            }
+            Ok(())
+        }
+        Command::List { names } => {
+            let found = processes::list_pcsx2_processes(&names.names());
+            if found.is_empty() {
+                bail!("no PCSX2 processes found named {}", names.names().join(", "));
+            }
+            for (index, process) in found.iter().enumerate() {
+                println!(
+                    "{}: PID {} {}, {}",
+                    index,
+                    process.pid,
+                    process.name,
+                    describe_game(process.pid)
+                );
+                println!("   {}", process.command_line.join(" "));
+            }
+            Ok(())
+        }
+        Command::Dump { output, process } => {
+            let (_, handle) = attach(&process)?;
+            let image =
+                Ps2MemoryImage::capture(&Ps2SeparateProcess { pcsx2_process_handle: handle })?;
+            image.save(&output)?;
+            log::info!("saved EE RAM to {}", output.display());
+            Ok(())
+        }
+        Command::ExportMotec { session, out_dir } => {
+            let out_dir = out_dir.unwrap_or_else(|| session.with_extension(""));
+            motec::export_session(&session, &out_dir)?;
+            Ok(())
+        }
+    }
+}
+
+/// How the race is shown
+enum View {
+    /// the overlay in a window of this size
+    Window([f32; 2]),
+    /// the timing tower in the terminal, redrawn this many times a second
+    Terminal(f32),
+}
+
+/// Finds PCSX2 once, for commands that don't wait for it to start
+fn attach(process: &ProcessArgs) -> Result<(Pcsx2Process, ProcessHandle)> {
+    let found = processes::find_pcsx2_process(process.target()?, &process.names.names())?;
+    let handle = processes::open_process(found.pid)?;
+    Ok((found, handle))
+}
+
+/// The game running in a PCSX2 process
+fn describe_game(pid: usize) -> String {
+    let version = processes::open_process(pid).and_then(|handle| {
+        Ok(GameVersion::detect(&Ps2SeparateProcess { pcsx2_process_handle: handle })?)
+    });
+    match version {
+        Ok(Some(version)) => version.to_string(),
+        Ok(None) => "no game found".to_owned(),
+        Err(e) => format!("couldn't read its memory: {:#}", e),
+    }
+}
+
+/// The config for one of several PCSX2s read at once. Files are kept apart in a directory for
+/// each, numbered as in `timing list` for those chosen by `--index` and named by PID otherwise,
+/// and the network outputs only go to the first.
+fn instance_config(config: &Config, instance: usize, target: Target) -> Config {
+    let mut config = config.clone();
+    let directory = match target {
+        Target::First => "instance-0".to_owned(),
+        Target::Index(index) => format!("instance-{}", index),
+        Target::Pid(pid) => format!("pid-{}", pid),
+    };
+    if let Some(telemetry) = &mut config.telemetry {
+        telemetry.directory = telemetry.directory.join(&directory);
+    }
+    if let Some(obs) = &mut config.obs {
+        obs.directory = obs.directory.join(&directory);
+    }
+    if instance > 0 {
+        config.server = None;
+        config.udp = None;
+    }
+    config
+}
+
+fn open_memory(memory: &MemoryArgs, config: &Config, view: View) -> Result<()> {
+    if memory.fake {
+        let game_data = GameData::from_image(Ps2MemoryImage::blank(), config);
+        let started = Instant::now();
+        run(game_data, view, move |game_data| {
+            let race_time = started.elapsed().as_millis() as TimeMs;
+            fake_race::write_fake_race(&mut game_data.ps2, race_time)
+        })
+    } else if let Some(path) = &memory.image {
+        let game_data = GameData::from_image(Ps2MemoryImage::load(path)?, config);
+        run(game_data, view, |_| Ok(()))
+    } else {
+        let names = memory.process.names.names();
+        let targets = memory.process.targets()?;
+        let poll = |game_data: &mut GameData<Pcsx2Connection>| -> Result<()> {
+            game_data.ps2.poll();
+            Ok(())
+        };
+        match (targets.as_slice(), view) {
+            ([target], view) => {
+                let connection =
+                    Pcsx2Connection::new(processes::resolve_target(*target, &names)?, names);
+                run(GameData::new(connection, config), view, poll)
+            }
+            (targets, View::Terminal(refresh_hz)) => {
+                let mut games = Vec::new();
+                for (instance, &target) in targets.iter().enumerate() {
+                    let resolved = processes::resolve_target(target, &names)?;
+                    let connection = Pcsx2Connection::new(resolved, names.clone());
+                    games.push(GameData::new(
+                        connection,
+                        &instance_config(config, instance, target),
+                    ));
+                }
+                headless::run(&mut games, refresh_hz, poll)
+            }
+            (_, View::Window(_)) => {
+                bail!("the overlay shows one PCSX2, use headless to read several at once")
+            }
+        }
+    }
+}
+
+/// Samples and shows the race until the window is closed or the process is stopped.
+/// `before_sample` runs ahead of each sample.
+fn run<M: Ps2Memory + 'static>(
+    mut game_data: GameData<M>,
+    view: View,
+    mut before_sample: impl FnMut(&mut GameData<M>) -> Result<()> + 'static,
+) -> Result<()> {
+    match view {
+        View::Terminal(refresh_hz) => {
+            headless::run(slice::from_mut(&mut game_data), refresh_hz, before_sample)
+        }
+        View::Window(window_size) => {
+            let mut ui_options = UiOptions::default();
+            let mut app = App::init("GT4 timing", window_size);
+            init_ui(&mut app.imgui, app.dpi_factor);
+            app.main_loop(
+                move |ui| {
+                    if let Err(e) = before_sample(&mut game_data) {
+                        log::error!("{:?}", e);
+                    }
+                    render_ui(ui, window_size, &mut game_data, &mut ui_options, false, 1.0)
+                },
+                || {},
+            );
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,357 @@
+use crate::{
+    game_data::TimeMs,
+    telemetry::{read_session, TelemetryRow},
+};
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// Layout of the MoTeC i2 .ld format, as written by the ADL loggers. Lap beacons don't live in the
+// .ld file itself, i2 reads them from an .ldx file of the same name.
+
+const HEADER_SIZE: u32 = 0x6E2;
+const EVENT_SIZE: u32 = 1154;
+const VENUE_SIZE: u32 = 1100;
+const VEHICLE_SIZE: u32 = 260;
+const CHANNEL_META_SIZE: u32 = 124;
+
+enum ChannelData {
+    F32(Vec<f32>),
+    I16(Vec<i16>),
+}
+
+impl ChannelData {
+    fn len(&self) -> usize {
+        match self {
+            ChannelData::F32(v) => v.len(),
+            ChannelData::I16(v) => v.len(),
+        }
+    }
+
+    fn byte_size(&self) -> u32 {
+        match self {
+            ChannelData::F32(v) => v.len() as u32 * 4,
+            ChannelData::I16(v) => v.len() as u32 * 2,
+        }
+    }
+
+    /// the two data type codes i2 expects
+    fn data_type(&self) -> (u16, u16) {
+        match self {
+            ChannelData::F32(_) => (0x07, 4),
+            ChannelData::I16(_) => (0x03, 2),
+        }
+    }
+}
+
+struct Channel {
+    name: &'static str,
+    short_name: &'static str,
+    unit: &'static str,
+    data: ChannelData,
+}
+
+/// Little endian writer for the fixed size records of an .ld file
+struct LdBuffer(Vec<u8>);
+
+impl LdBuffer {
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i16(&mut self, v: i16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn zeros(&mut self, n: usize) {
+        self.0.resize(self.0.len() + n, 0);
+    }
+
+    /// a zero padded string field, truncated if too long
+    fn str(&mut self, s: &str, len: usize) {
+        let bytes: Vec<u8> = s.bytes().filter(u8::is_ascii).take(len).collect();
+        self.0.extend_from_slice(&bytes);
+        self.zeros(len - bytes.len());
+    }
+}
+
+/// Converts a telemetry session into one .ld and .ldx file per car, returning the .ld files written
+pub fn export_session(session: &Path, out_dir: &Path) -> Result<Vec<PathBuf>> {
+    let rows = read_session(session)?;
+    if rows.is_empty() {
+        bail!("no samples in {}", session.display());
+    }
+    let recorded = fs::metadata(session)?.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    let stem = session.file_stem().context("session file has no name")?.to_string_lossy();
+    fs::create_dir_all(out_dir)?;
+
+    let mut by_car: BTreeMap<usize, Vec<TelemetryRow>> = BTreeMap::new();
+    for row in rows {
+        by_car.entry(row.car).or_default().push(row);
+    }
+    let mut written = Vec::new();
+    for (car, mut rows) in by_car {
+        rows.sort_by_key(|row| row.race_time);
+        let path = out_dir.join(format!("{}-car{}.ld", stem, car));
+        let frequency = sample_frequency(&rows);
+        let channels = channels(&rows, frequency);
+        let beacons = lap_beacons(&rows);
+        let details = LdDetails { name: &rows[0].name, event: &stem, recorded };
+        fs::write(&path, ld_file(&channels, frequency, &details))
+            .with_context(|| format!("writing {}", path.display()))?;
+        fs::write(path.with_extension("ldx"), ldx_file(&beacons))
+            .with_context(|| format!("writing lap beacons for {}", path.display()))?;
+        log::info!("exported car {} to {}", car, path.display());
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Samples per second, guessed from the typical interval between logged samples
+fn sample_frequency(rows: &[TelemetryRow]) -> u16 {
+    let mut intervals: Vec<TimeMs> = rows
+        .windows(2)
+        .map(|pair| pair[1].race_time - pair[0].race_time)
+        .filter(|&i| i > 0)
+        .collect();
+    intervals.sort();
+    match intervals.get(intervals.len() / 2) {
+        Some(&interval) => (1000.0 / interval as f32).round().max(1.0).min(1000.0) as u16,
+        None => 1,
+    }
+}
+
+/// Resamples the rows at a fixed frequency, holding each value until the next sample
+fn resample(rows: &[TelemetryRow], frequency: u16) -> Vec<&TelemetryRow> {
+    let start = rows[0].race_time as f32;
+    let end = rows[rows.len() - 1].race_time as f32;
+    let step = 1000.0 / frequency as f32;
+    let mut resampled = Vec::new();
+    let mut next = 0;
+    let mut t = start;
+    while t <= end {
+        while next + 1 < rows.len() && rows[next + 1].race_time as f32 <= t {
+            next += 1;
+        }
+        resampled.push(&rows[next]);
+        t += step;
+    }
+    resampled
+}
+
+fn channels(rows: &[TelemetryRow], frequency: u16) -> Vec<Channel> {
+    let samples = resample(rows, frequency);
+    let f32_channel = |name, short_name, unit, value: &dyn Fn(&TelemetryRow) -> f32| Channel {
+        name,
+        short_name,
+        unit,
+        data: ChannelData::F32(samples.iter().map(|row| value(row)).collect()),
+    };
+    vec![
+        f32_channel("Ground Speed", "Speed", "km/h", &|row| row.speed.unwrap_or(0.0) * 3.6),
+        f32_channel("Engine RPM", "RPM", "rpm", &|row| row.rpm),
+        f32_channel("Throttle Pos", "Thr", "%", &|row| row.throttle_pedal * 100.0),
+        f32_channel("Brake Pos", "Brk", "%", &|row| row.brake1 * 100.0),
+        f32_channel("Lap Distance", "LapDst", "m", &|row| row.meters_driven_in_current_lap),
+        Channel {
+            name: "Gear",
+            short_name: "Gear",
+            unit: "",
+            data: ChannelData::I16(samples.iter().map(|row| row.gear as i16).collect()),
+        },
+        Channel {
+            name: "Lap Number",
+            short_name: "Lap",
+            unit: "",
+            data: ChannelData::I16(samples.iter().map(|row| row.lap).collect()),
+        },
+        // 0 where the car looked misread, so the other channels can't be trusted
+        Channel {
+            name: "Read Valid",
+            short_name: "Valid",
+            unit: "",
+            data: ChannelData::I16(samples.iter().map(|row| row.valid as i16).collect()),
+        },
+    ]
+}
+
+/// Times when a new lap started, in ms from the start of the recording
+fn lap_beacons(rows: &[TelemetryRow]) -> Vec<TimeMs> {
+    let start = rows[0].race_time;
+    rows.windows(2)
+        .filter(|pair| pair[1].lap > pair[0].lap)
+        .map(|pair| pair[1].race_time - start)
+        .collect()
+}
+
+struct LdDetails<'a> {
+    name: &'a str,
+    event: &'a str,
+    /// unix time in seconds
+    recorded: u64,
+}
+
+fn ld_file(channels: &[Channel], frequency: u16, details: &LdDetails) -> Vec<u8> {
+    let event_ptr = HEADER_SIZE;
+    let venue_ptr = event_ptr + EVENT_SIZE;
+    let vehicle_ptr = venue_ptr + VENUE_SIZE;
+    let meta_ptr = vehicle_ptr + VEHICLE_SIZE;
+    let data_ptr = meta_ptr + CHANNEL_META_SIZE * channels.len() as u32;
+    let (date, time) = civil_date_time(details.recorded);
+
+    let mut b = LdBuffer(Vec::new());
+    // header
+    b.u32(0x40);
+    b.zeros(4);
+    b.u32(meta_ptr);
+    b.u32(data_ptr);
+    b.zeros(20);
+    b.u32(event_ptr);
+    b.zeros(24);
+    b.u16(1);
+    b.u16(0x4240);
+    b.u16(0xf);
+    b.u32(0x1f44); // device serial
+    b.str("ADL", 8);
+    b.u16(420); // device version
+    b.u16(0xadb0);
+    b.u32(channels.len() as u32);
+    b.zeros(4);
+    b.str(&date, 16);
+    b.zeros(16);
+    b.str(&time, 16);
+    b.zeros(16);
+    b.str(details.name, 64); // driver
+    b.str(details.name, 64); // vehicle
+    b.zeros(64);
+    b.str("Gran Turismo 4", 64); // venue
+    b.zeros(64 + 1024);
+    b.u32(0xc81a4); // pro logging
+    b.zeros(66);
+    b.str(details.event, 64); // short comment
+    b.zeros(126);
+    debug_assert_eq!(b.0.len() as u32, event_ptr);
+
+    // event
+    b.str(details.event, 64);
+    b.str("", 64); // session
+    b.str("exported from GT4 timing overlay telemetry", 1024);
+    b.u16(venue_ptr as u16);
+    // venue
+    b.str("Gran Turismo 4", 64);
+    b.zeros(1034);
+    b.u16(vehicle_ptr as u16);
+    // vehicle
+    b.str(details.name, 64);
+    b.zeros(128);
+    b.u32(0); // weight
+    b.str("Car", 32);
+    b.str("", 32);
+    debug_assert_eq!(b.0.len() as u32, meta_ptr);
+
+    let mut channel_data_ptr = data_ptr;
+    for (n, channel) in channels.iter().enumerate() {
+        let this_meta = meta_ptr + CHANNEL_META_SIZE * n as u32;
+        let prev = if n == 0 { 0 } else { this_meta - CHANNEL_META_SIZE };
+        let next = if n + 1 == channels.len() { 0 } else { this_meta + CHANNEL_META_SIZE };
+        let (data_type_a, data_type) = channel.data.data_type();
+        b.u32(prev);
+        b.u32(next);
+        b.u32(channel_data_ptr);
+        b.u32(channel.data.len() as u32);
+        b.u16(0x2ee1 + n as u16);
+        b.u16(data_type_a);
+        b.u16(data_type);
+        b.u16(frequency);
+        b.i16(0); // shift
+        b.i16(1); // multiplier
+        b.i16(1); // scale
+        b.i16(0); // decimal places
+        b.str(channel.name, 32);
+        b.str(channel.short_name, 8);
+        b.str(channel.unit, 12);
+        b.zeros(40);
+        channel_data_ptr += channel.data.byte_size();
+    }
+    debug_assert_eq!(b.0.len() as u32, data_ptr);
+
+    for channel in channels {
+        match &channel.data {
+            ChannelData::F32(values) => {
+                values.iter().for_each(|v| b.0.extend_from_slice(&v.to_le_bytes()))
+            }
+            ChannelData::I16(values) => values.iter().for_each(|&v| b.i16(v)),
+        }
+    }
+    b.0
+}
+
+fn ldx_file(beacons: &[TimeMs]) -> String {
+    let mut markers = String::new();
+    for (n, &time) in beacons.iter().enumerate() {
+        markers += &format!(
+            "     <Marker Version=\"100\" ClassName=\"BCN\" Name=\"Manual.{}\" Flags=\"77\" Time=\"{}.000000\"/>\n",
+            n + 1,
+            time as i64 * 1000
+        );
+    }
+    let mut details = format!("   <String Id=\"Total Laps\" Value=\"{}\"/>\n", beacons.len());
+    // only laps between two beacons are complete
+    let fastest =
+        beacons.windows(2).map(|pair| pair[1] - pair[0]).enumerate().min_by_key(|&(_, t)| t);
+    if let Some((lap, time)) = fastest {
+        details += &format!(
+            "   <String Id=\"Fastest Time\" Value=\"{}:{:02}.{:03}\"/>\n   <String Id=\"Fastest Lap\" Value=\"{}\"/>\n",
+            time / 60000,
+            time / 1000 % 60,
+            time % 1000,
+            lap + 2
+        );
+    }
+    format!(
+        "<?xml version=\"1.0\"?>
+<LDXFile Locale=\"English_United Kingdom.1252\" DefaultLocale=\"C\" Version=\"1.6\">
+ <Layers>
+  <Layer>
+   <MarkerBlock>
+    <MarkerGroup Name=\"Beacons\" Index=\"3\">
+{}    </MarkerGroup>
+   </MarkerBlock>
+   <RangeBlock/>
+  </Layer>
+  <Details>
+{}  </Details>
+ </Layers>
+</LDXFile>
+",
+        markers, details
+    )
+}
+
+/// UTC date as dd/mm/yyyy and time as hh:mm:ss, from unix seconds
+fn civil_date_time(unix_seconds: u64) -> (String, String) {
+    let days = (unix_seconds / 86400) as i64;
+    let secs_of_day = unix_seconds % 86400;
+    // days to civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        format!("{:02}/{:02}/{}", day, month, year),
+        format!("{:02}:{:02}:{:02}", secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60),
+    )
+}
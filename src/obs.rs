@@ -0,0 +1,213 @@
+use crate::{
+    game_data::{format_race_time, RaceState},
+    sinks::RaceSink,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// Files for OBS to show as text and browser sources, so the standings can go on stream without
+// capturing the emulator.
+
+const ROW_START: &str = "<!-- row -->";
+const ROW_END: &str = "<!-- /row -->";
+
+/// Used when no template is configured. The part between the row markers is repeated for each
+/// car in race order.
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{{refresh_seconds}}">
+<style>
+body { background: transparent; color: white; font: bold 20px sans-serif; margin: 0; }
+table { border-collapse: collapse; }
+td { background: rgba(0, 0, 0, 0.6); padding: 2px 8px; }
+.position { text-align: right; }
+.gap { text-align: right; }
+.player td { color: rgb(255, 190, 50); }
+.misread td { opacity: 0.5; }
+</style>
+</head>
+<body>
+<table>
+<!-- row -->
+<tr class="{{class}}"><td class="position">{{position}}</td><td class="name" style="border-left: 6px solid {{colour}}">{{name}}</td><td class="gap">{{gap}}</td></tr>
+<!-- /row -->
+</table>
+</body>
+</html>
+"#;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ObsConfig {
+    pub directory: PathBuf,
+    /// how often the files are rewritten
+    pub interval_ms: u64,
+    /// HTML with `{{refresh_seconds}}` and `{{race_time}}` placeholders, and a row between
+    /// `<!-- row -->` and `<!-- /row -->` using `{{position}}`, `{{name}}`, `{{manufacturer}}`,
+    /// `{{car_class}}`, `{{colour}}`, `{{gap}}` and `{{class}}`, which is `player` for the player's
+    /// car and `misread` for cars whose values look wrong
+    pub template: Option<PathBuf>,
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        ObsConfig { directory: PathBuf::from("obs"), interval_ms: 500, template: None }
+    }
+}
+
+/// An HTML template split around the part repeated for each car
+struct Template {
+    header: String,
+    row: String,
+    footer: String,
+}
+
+impl Template {
+    fn parse(text: &str) -> Result<Template> {
+        let (header, rest) = match text.find(ROW_START) {
+            Some(start) => text.split_at(start),
+            None => bail!("template has no {} marker", ROW_START),
+        };
+        let rest = &rest[ROW_START.len()..];
+        let (row, footer) = match rest.find(ROW_END) {
+            Some(end) => rest.split_at(end),
+            None => bail!("template has no {} marker", ROW_END),
+        };
+        Ok(Template {
+            header: header.to_owned(),
+            row: row.to_owned(),
+            footer: footer[ROW_END.len()..].to_owned(),
+        })
+    }
+}
+
+/// One line of the standings
+struct Standing {
+    position: usize,
+    name: String,
+    manufacturer: String,
+    car_class: String,
+    /// CSS colour, `transparent` if the car hasn't one
+    colour: String,
+    gap: String,
+    is_player: bool,
+    /// false if the car's values look misread
+    valid: bool,
+}
+
+/// Writes `standings.txt` with every car, `position-<n>.txt` with one car each and
+/// `standings.html` from the template
+pub struct ObsWriter {
+    config: ObsConfig,
+    template: Template,
+    last_written: Option<Instant>,
+}
+
+impl ObsWriter {
+    pub fn new(config: ObsConfig) -> Result<Self> {
+        let template = match &config.template {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("reading OBS template {}", path.display()))?;
+                Template::parse(&text)
+                    .with_context(|| format!("parsing OBS template {}", path.display()))?
+            }
+            None => Template::parse(DEFAULT_TEMPLATE)?,
+        };
+        fs::create_dir_all(&config.directory)
+            .with_context(|| format!("creating {}", config.directory.display()))?;
+        log::info!("writing OBS standings to {}", config.directory.display());
+        Ok(ObsWriter { config, template, last_written: None })
+    }
+
+    fn render_html(&self, race: &RaceState, standings: &[Standing]) -> String {
+        let refresh_seconds = (self.config.interval_ms as f32 / 1000.0).max(1.0).ceil();
+        let race_time = format_race_time(race.race_time);
+        let fill = |text: &str| {
+            text.replace("{{refresh_seconds}}", &refresh_seconds.to_string())
+                .replace("{{race_time}}", &race_time)
+        };
+        let mut html = fill(&self.template.header);
+        for standing in standings {
+            let classes = [(standing.is_player, "player"), (!standing.valid, "misread")];
+            let class: Vec<_> = classes.iter().filter(|(has, _)| *has).map(|(_, c)| *c).collect();
+            let class = class.join(" ");
+            html += &fill(&self.template.row)
+                .replace("{{position}}", &standing.position.to_string())
+                .replace("{{name}}", &escape_html(&standing.name))
+                .replace("{{manufacturer}}", &escape_html(&standing.manufacturer))
+                .replace("{{car_class}}", &escape_html(&standing.car_class))
+                .replace("{{colour}}", &standing.colour)
+                .replace("{{gap}}", &escape_html(&standing.gap))
+                .replace("{{class}}", &class);
+        }
+        html += &fill(&self.template.footer);
+        html
+    }
+}
+
+impl RaceSink for ObsWriter {
+    fn publish(&mut self, race: &RaceState) -> Result<()> {
+        let interval = Duration::from_millis(self.config.interval_ms);
+        if self.last_written.map_or(false, |last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last_written = Some(Instant::now());
+
+        let mut standings: Vec<_> = (0..race.cars.len())
+            .map(|i| Standing {
+                position: race.positions[i],
+                name: race.labels[i].name.clone(),
+                manufacturer: race.labels[i].manufacturer.clone().unwrap_or_default(),
+                car_class: race.labels[i].class.clone().unwrap_or_default(),
+                colour: race.labels[i].colour_hex().unwrap_or_else(|| "transparent".to_owned()),
+                gap: match race.gaps_to_leader[i] {
+                    _ if race.positions[i] == 1 => "Leader".to_owned(),
+                    Some(gap) => format!("+{:.3}", gap / 1000.0),
+                    None => "-".to_owned(),
+                },
+                is_player: race.is_player[i],
+                valid: race.validations[i].is_valid(),
+            })
+            .collect();
+        standings.sort_by_key(|standing| standing.position);
+
+        let mut all = String::new();
+        for standing in &standings {
+            let mut line = format!("{}. {} {}", standing.position, standing.name, standing.gap);
+            if !standing.valid {
+                line += " (misread)";
+            }
+            write_atomically(
+                &self.config.directory.join(format!("position-{}.txt", standing.position)),
+                &line,
+            )?;
+            all += &line;
+            all += "\n";
+        }
+        write_atomically(&self.config.directory.join("standings.txt"), &all)?;
+        let html = self.render_html(race, &standings);
+        write_atomically(&self.config.directory.join("standings.html"), &html)?;
+        Ok(())
+    }
+}
+
+/// Writes next to the file then renames it over, so OBS never reads half a file
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, contents).with_context(|| format!("writing {}", temporary.display()))?;
+    fs::rename(&temporary, path).with_context(|| format!("replacing {}", path.display()))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,211 @@
+use crate::game_data::{lap_times, Automobile, Checkpoints, TimeMs};
+use ordered_float::OrderedFloat;
+
+/// a car going slower than this is standing still, in m/s
+const STATIONARY_SPEED: f32 = 0.5;
+/// a car has to stand still for this long before it counts as a pit stop, which filters out
+/// spins and stalls that recover quickly
+const MIN_STOP_MS: TimeMs = 2000;
+/// how far either side of the start line the pit boxes can be, in metres
+const PIT_BOX_METRES: f32 = 600.0;
+
+// No pit lane flag has been found in `Automobile`, so a pit stop is inferred from a car standing
+// still near the start line, where the pit boxes are, between the start and the finish. A car
+// that spins or stops on the main straight still counts, and without the race length every
+// stop after the finish does too. The times are when the car stopped and for how long, not when
+// it entered or left the pit lane.
+
+#[derive(Clone, Debug)]
+pub struct PitStop {
+    /// lap the car was on when it stopped
+    pub lap: i16,
+    /// how far through the lap the car stopped, from 0 to 1
+    pub lap_fraction: f32,
+    /// race time the car stopped at
+    pub stopped_at: TimeMs,
+    /// time spent stationary, still counting while the car is stopped
+    pub stationary_ms: TimeMs,
+    /// whether the car has moved off again
+    pub moved_off: bool,
+}
+
+#[derive(Default)]
+pub struct PitTracker {
+    pub stops: Vec<PitStop>,
+    last_sample: Option<(f32, TimeMs)>,
+    stationary_since: Option<TimeMs>,
+}
+
+impl PitTracker {
+    pub fn reset(&mut self) {
+        *self = PitTracker::default();
+    }
+
+    /// `race_laps` is the length of the race if known, so stops after the finish can be ignored
+    pub fn update(
+        &mut self,
+        car: &Automobile,
+        track_length: f32,
+        race_time: TimeMs,
+        race_laps: Option<i16>,
+    ) {
+        let progress = car.progress(track_length).into_inner();
+        let last_sample = self.last_sample.replace((progress, race_time));
+        if progress < 1.0 {
+            // still on the grid
+            return;
+        }
+        if race_laps.map_or(false, |laps| progress >= laps as f32 + 1.0) {
+            // past the flag, where every car stops
+            return;
+        }
+        let (last_progress, last_time) = match last_sample {
+            Some(sample) => sample,
+            None => return,
+        };
+        if race_time == last_time {
+            // game paused, or no new frame since the last sample
+            return;
+        }
+        let moved_metres = (progress - last_progress) * track_length;
+        let speed = moved_metres.abs() / ((race_time - last_time) as f32 / 1000.0);
+        if speed < STATIONARY_SPEED {
+            let since = *self.stationary_since.get_or_insert(last_time);
+            let stationary_ms = race_time - since;
+            let metres_from_line = progress.fract().min(1.0 - progress.fract()) * track_length;
+            match self.stops.last_mut() {
+                Some(stop) if !stop.moved_off => stop.stationary_ms = stationary_ms,
+                _ if stationary_ms >= MIN_STOP_MS && metres_from_line <= PIT_BOX_METRES => {
+                    self.stops.push(PitStop {
+                        lap: car.implicit_current_lap(),
+                        lap_fraction: progress.fract(),
+                        stopped_at: since,
+                        stationary_ms,
+                        moved_off: false,
+                    })
+                }
+                _ => {}
+            }
+        } else {
+            self.stationary_since = None;
+            if let Some(stop) = self.stops.last_mut() {
+                stop.moved_off = true;
+            }
+        }
+    }
+
+    /// Time lost to pit stops compared with the car's typical lap, in ms.
+    /// Each stop costs its in-lap and out-lap, so it is only counted once both are complete.
+    pub fn pit_laps_loss(&self, checkpoints: &Checkpoints) -> Option<f32> {
+        if self.stops.is_empty() {
+            return None;
+        }
+        let laps = lap_times(checkpoints);
+        let pit_laps: Vec<(i32, i32)> = self
+            .stops
+            .iter()
+            .map(|stop| {
+                let lap = stop.lap as i32;
+                // pit boxes near the line are reached at the end of the in-lap
+                if stop.lap_fraction < 0.5 {
+                    (lap - 1, lap)
+                } else {
+                    (lap, lap + 1)
+                }
+            })
+            .collect();
+        let mut clean_laps: Vec<f32> = laps
+            .iter()
+            .filter(|(lap, _)| {
+                pit_laps.iter().all(|&(in_lap, out_lap)| *lap != in_lap && *lap != out_lap)
+            })
+            .map(|&(_, time)| time)
+            .collect();
+        if clean_laps.is_empty() {
+            return None;
+        }
+        clean_laps.sort_by_key(|&t| OrderedFloat(t));
+        let typical_lap = clean_laps[clean_laps.len() / 2];
+
+        let lap_time = |lap: i32| laps.iter().find(|(l, _)| *l == lap).map(|&(_, t)| t);
+        let mut loss = None;
+        for (in_lap, out_lap) in pit_laps {
+            if let (Some(in_time), Some(out_time)) = (lap_time(in_lap), lap_time(out_lap)) {
+                *loss.get_or_insert(0.0) += in_time + out_time - 2.0 * typical_lap;
+            }
+        }
+        loss
+    }
+}
+
+/// Pit stop totals for one car
+#[derive(Clone, Debug)]
+pub struct PitSummary {
+    pub stops: usize,
+    /// whether the car is stopped near the line right now
+    pub in_pit: bool,
+    pub stationary_ms: TimeMs,
+    /// total time the in-laps and out-laps of stops took over a typical lap, once the out-lap of
+    /// a stop has been completed
+    pub pit_laps_loss_ms: Option<f32>,
+}
+
+impl PitSummary {
+    pub fn of(tracker: &PitTracker, checkpoints: &Checkpoints) -> Self {
+        PitSummary {
+            stops: tracker.stops.len(),
+            in_pit: tracker.stops.last().map_or(false, |stop| !stop.moved_off),
+            stationary_ms: tracker.stops.iter().map(|stop| stop.stationary_ms).sum(),
+            pit_laps_loss_ms: tracker.pit_laps_loss(checkpoints),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK_LENGTH: f32 = 5807.0;
+
+    /// Samples a car at 60 Hz for `seconds`, going at `speed` in m/s, and returns where it got to
+    fn drive(
+        pits: &mut PitTracker,
+        start: (f32, TimeMs),
+        speed: f32,
+        seconds: f32,
+    ) -> (f32, TimeMs) {
+        let (mut metres, mut race_time) = start;
+        let mut car = Automobile::zeroed();
+        car.set_implicit_current_lap(2);
+        for sample in 1..=(seconds * 60.0) as i32 {
+            let time = start.1 + sample * 1000 / 60;
+            metres += speed * (time - race_time) as f32 / 1000.0;
+            race_time = time;
+            car.set_meters_driven_in_current_lap(metres);
+            pits.update(&car, TRACK_LENGTH, race_time, None);
+        }
+        (metres, race_time)
+    }
+
+    #[test]
+    fn a_slow_car_near_the_line_isnt_stopped() {
+        // 72 km/h, which moves a third of a metre a sample
+        let mut pits = PitTracker::default();
+        drive(&mut pits, (100.0, 60_000), 20.0, 3.0);
+        assert!(pits.stops.is_empty());
+    }
+
+    #[test]
+    fn a_car_standing_still_near_the_line_stops() {
+        let mut pits = PitTracker::default();
+        let arrived = drive(&mut pits, (100.0, 60_000), 20.0, 1.0);
+        let stopped = drive(&mut pits, arrived, 0.0, 3.0);
+        drive(&mut pits, stopped, 10.0, 1.0);
+
+        assert_eq!(pits.stops.len(), 1);
+        let stop = &pits.stops[0];
+        assert_eq!(stop.stopped_at, arrived.1);
+        assert_eq!(stop.stationary_ms, stopped.1 - arrived.1);
+        assert!(stop.moved_off);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::game_data::{Automobile, MAX_CARS};
+use std::fmt;
+
+// No flag saying which car is the player's has been found in memory, so it is set in the config,
+// and taken to be the first car if it isn't. The pedals can only hint at which it is: the pad
+// reports how far a button is pressed as a byte, so a car driven from an analogue pad should only
+// ever have its throttle and brake part pressed in whole steps of 1/255, while the AI's are worked
+// out as floats and land between them. That hasn't been checked against the game, and a keyboard
+// or digital pad never part presses a pedal, so it is only shown as a suggestion.
+
+/// part-pressed pedal readings in whole pad steps needed before a car is taken to be the player's
+const SAMPLES_NEEDED: u32 = 30;
+/// pad steps in a fully pressed pedal
+const PAD_STEPS: f32 = 255.0;
+/// how far from a whole step a reading can be and still count as one
+const STEP_TOLERANCE: f32 = 0.001;
+
+/// How the player's car was found
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerSource {
+    /// set as `player_slot` in the config
+    Config,
+    /// not set, so taken to be the first car
+    Assumed,
+}
+
+impl fmt::Display for PlayerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlayerSource::Config => "set in the config",
+            PlayerSource::Assumed => "assumed to be the first car",
+        })
+    }
+}
+
+pub struct PlayerDetector {
+    /// for each car, how many part-pressed pedal readings were in whole pad steps, or `None` once
+    /// one wasn't
+    pad_readings: [Option<u32>; MAX_CARS],
+}
+
+impl Default for PlayerDetector {
+    fn default() -> Self {
+        PlayerDetector { pad_readings: [Some(0); MAX_CARS] }
+    }
+}
+
+impl PlayerDetector {
+    pub fn reset(&mut self) {
+        *self = PlayerDetector::default();
+    }
+
+    pub fn update(&mut self, cars: &[Automobile]) {
+        for (readings, car) in self.pad_readings.iter_mut().zip(cars) {
+            for pedal in [car.throttle_pedal(), car.brake1()].iter() {
+                if *pedal <= 0.0 || *pedal >= 1.0 {
+                    continue;
+                }
+                let steps = pedal * PAD_STEPS;
+                if (steps - steps.round()).abs() < STEP_TOLERANCE {
+                    *readings = readings.map(|count| count + 1);
+                } else {
+                    *readings = None;
+                }
+            }
+        }
+    }
+
+    /// The one car that has only been driven in pad steps for long enough, if there is just one,
+    /// as a suggestion for `player_slot`
+    pub fn hint(&self) -> Option<usize> {
+        let mut candidates = (0..MAX_CARS)
+            .filter(|&i| self.pad_readings[i].map_or(false, |count| count >= SAMPLES_NEEDED));
+        match (candidates.next(), candidates.next()) {
+            (Some(player), None) => Some(player),
+            _ => None,
+        }
+    }
+}
+
+/// The player's car from the config, or else the first car
+pub fn player(configured: Option<usize>) -> (usize, PlayerSource) {
+    match configured {
+        Some(slot) => (slot, PlayerSource::Config),
+        None => (0, PlayerSource::Assumed),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::game_data::{Automobile, TimeMs};
+use std::cmp::Reverse;
+
+/// how far a car has to get ahead of the car it is passing before the pass counts, in metres.
+/// Stops positions flickering back and forth while cars are side by side.
+const OVERTAKE_MARGIN_METRES: f32 = 5.0;
+
+#[derive(Clone, Debug)]
+pub struct Overtake {
+    pub race_time: TimeMs,
+    /// lap the overtaking car was on
+    pub lap: i16,
+    pub overtaking_car: usize,
+    pub overtaken_car: usize,
+    /// position of the overtaking car after the pass, counting from 1
+    pub new_position: usize,
+}
+
+/// Keeps track of race positions and logs every overtake between cars that look correctly read
+#[derive(Default)]
+pub struct PositionTracker {
+    /// car indices in race order, only changed once a pass has been confirmed
+    order: Vec<usize>,
+    pub overtakes: Vec<Overtake>,
+}
+
+impl PositionTracker {
+    pub fn reset(&mut self) {
+        self.order.clear();
+        self.overtakes.clear();
+    }
+
+    /// The position of each car, counting from 1
+    pub fn positions(&self) -> Vec<usize> {
+        let mut positions = vec![0; self.order.len()];
+        for (position, &car) in self.order.iter().enumerate() {
+            positions[car] = position + 1;
+        }
+        positions
+    }
+
+    /// `valid` is whether each car looks correctly read. Misread cars are still put in order, but
+    /// passes they are part of aren't logged.
+    pub fn update(
+        &mut self,
+        cars: &[Automobile],
+        valid: &[bool],
+        track_length: f32,
+        race_time: TimeMs,
+    ) {
+        let progress: Vec<_> = cars.iter().map(|c| c.progress(track_length).into_inner()).collect();
+        if self.order.len() != cars.len() {
+            self.order = (0..cars.len()).collect();
+            self.order.sort_by_key(|&i| Reverse(cars[i].progress(track_length)));
+            return;
+        }
+        let margin = OVERTAKE_MARGIN_METRES / track_length;
+        // bubble cars forward until nobody is clearly ahead of the car in front, so a car passing
+        // several others in one sample gets an overtake for each of them
+        let mut swapped = true;
+        while swapped {
+            swapped = false;
+            for position in 1..self.order.len() {
+                let ahead = self.order[position - 1];
+                let behind = self.order[position];
+                if progress[behind] - progress[ahead] > margin {
+                    self.order.swap(position - 1, position);
+                    swapped = true;
+                    // cars shuffling on the grid before the start are not overtakes
+                    if progress[ahead] >= 1.0 && valid[ahead] && valid[behind] {
+                        self.overtakes.push(Overtake {
+                            race_time,
+                            lap: cars[behind].implicit_current_lap(),
+                            overtaking_car: behind,
+                            overtaken_car: ahead,
+                            new_position: position,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,148 @@
-use sysinfo::{ProcessExt, SystemExt};
+use anyhow::{bail, Context, Result};
+use process_memory::{Architecture, Pid, ProcessHandle, ProcessHandleExt, TryIntoProcessHandle};
+use std::path::Path;
+use sysinfo::{Process, ProcessExt, System, SystemExt};
+use winapi::{
+    shared::minwindef::FALSE,
+    um::{processthreadsapi::GetCurrentProcess, winnt::HANDLE, wow64apiset::IsWow64Process},
+};
 
-pub fn get_pcsx2_process_id() -> usize {
-    let mut system = sysinfo::System::new_all();
+// TODO: read the 64 bit PCSX2 1.7 and later, running as pcsx2-qt, pcsx2x64 or an AppImage. They
+// don't keep the PS2's memory at fixed addresses, so where it is has to be found from the `EEmem`
+// the executable exports, and the handle opened as 64 bit. Until then they are refused.
+
+/// The name PCSX2 1.6 and earlier run under
+pub const PCSX2_PROCESS_NAMES: [&str; 1] = ["pcsx2"];
+
+/// A running process that looks like PCSX2
+pub struct Pcsx2Process {
+    pub pid: usize,
+    pub name: String,
+    pub command_line: Vec<String>,
+}
+
+impl Pcsx2Process {
+    fn of(process: &Process) -> Self {
+        Pcsx2Process {
+            pid: process.pid() as usize,
+            name: process.name().to_owned(),
+            command_line: process.cmd().to_vec(),
+        }
+    }
+}
+
+/// Which PCSX2 to read when there may be several
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// the one with the lowest PID
+    First,
+    Pid(usize),
+    /// counting from 0 in order of PID, as listed by `timing list`
+    Index(usize),
+}
+
+/// Processes going by one of the given names, or running an executable of that name, in order
+/// of PID
+pub fn find_pcsx2_processes(system: &mut System, names: &[String]) -> Vec<Pcsx2Process> {
     system.refresh_processes();
-    let candidates = system.get_process_by_name("pcsx2");
-    if candidates.len() == 0 {
-        panic!("no pcsx2 process found")
-    } else {
-        return candidates[0].pid();
+    let mut found: Vec<_> = system
+        .get_processes()
+        .values()
+        .filter(|process| is_pcsx2(process, names))
+        .map(Pcsx2Process::of)
+        .collect();
+    found.sort_by_key(|process| process.pid);
+    found
+}
+
+pub fn find_target(system: &mut System, target: Target, names: &[String]) -> Option<Pcsx2Process> {
+    match target {
+        Target::First => find_pcsx2_processes(system, names).into_iter().next(),
+        Target::Pid(pid) => find_process(system, pid),
+        Target::Index(index) => find_pcsx2_processes(system, names).into_iter().nth(index),
     }
 }
+
+pub fn list_pcsx2_processes(names: &[String]) -> Vec<Pcsx2Process> {
+    find_pcsx2_processes(&mut System::new(), names)
+}
+
+/// Pins a target chosen by index to the PID it has now, so it can't become another PCSX2 when
+/// the one it was exits and the rest are numbered again. The first PCSX2 is still looked for
+/// whenever it isn't running.
+pub fn resolve_target(target: Target, names: &[String]) -> Result<Target> {
+    match target {
+        Target::First => Ok(Target::First),
+        target => Ok(Target::Pid(find_pcsx2_process(target, names)?.pid)),
+    }
+}
+
+/// Finds the process to read once, for commands that don't wait for PCSX2 to start
+pub fn find_pcsx2_process(target: Target, names: &[String]) -> Result<Pcsx2Process> {
+    match find_target(&mut System::new(), target, names) {
+        Some(process) => Ok(process),
+        None => match target {
+            Target::First => bail!(
+                "no PCSX2 process found named {}. Start PCSX2 first, or choose it with --pid or \
+                 --process-name",
+                names.join(", ")
+            ),
+            Target::Pid(pid) => bail!("there is no process {}", pid),
+            Target::Index(index) => {
+                bail!("there is no PCSX2 number {}, see `timing list` for those running", index)
+            }
+        },
+    }
+}
+
+fn is_pcsx2(process: &Process, names: &[String]) -> bool {
+    let file_name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().into_owned());
+    let executable = file_name(process.exe());
+    let command = process.cmd().first().and_then(|command| file_name(Path::new(command)));
+    let mut candidates =
+        Some(process.name().to_owned()).into_iter().chain(executable).chain(command);
+    candidates.any(|candidate| {
+        let candidate = candidate.to_lowercase();
+        let candidate = candidate.trim_end_matches(".exe");
+        names.iter().any(|name| candidate == name.to_lowercase())
+    })
+}
+
+/// The process with the given PID, if it is running
+pub fn find_process(system: &mut System, pid: usize) -> Option<Pcsx2Process> {
+    system.refresh_processes();
+    system
+        .get_processes()
+        .values()
+        .find(|process| process.pid() as usize == pid)
+        .map(Pcsx2Process::of)
+}
+
+/// Opens a 32 bit PCSX2 to read, refusing 64 bit processes, which can't be read
+pub fn open_process(pid: usize) -> Result<ProcessHandle> {
+    let handle = (pid as Pid)
+        .try_into_process_handle()
+        .with_context(|| format!("opening process {}", pid))?;
+    if is_64_bit(handle.0).with_context(|| format!("checking process {} is 32 bit", pid))? {
+        bail!(
+            "process {} is 64 bit. Only the 32 bit PCSX2 1.6 and earlier can be read so far, since \
+             later versions keep the PS2's memory somewhere else",
+            pid
+        );
+    }
+    Ok(handle.set_arch(Architecture::Arch32Bit))
+}
+
+/// Whether the process is 64 bit, which it is on 64 bit Windows unless it runs under WOW64
+fn is_64_bit(process: HANDLE) -> Result<bool> {
+    let is_wow64 = |process| {
+        let mut wow64 = FALSE;
+        match unsafe { IsWow64Process(process, &mut wow64) } {
+            FALSE => Err(std::io::Error::last_os_error()),
+            _ => Ok(wow64 != FALSE),
+        }
+    };
+    let windows_is_64_bit =
+        cfg!(target_pointer_width = "64") || is_wow64(unsafe { GetCurrentProcess() })?;
+    Ok(windows_is_64_bit && !is_wow64(process)?)
+}
@@ -0,0 +1,162 @@
+use crate::game_data::{lap_times, time_at_progress, Checkpoints, TimeMs};
+use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+
+/// how many of a car's most recent laps are averaged to estimate its pace
+const RECENT_LAPS: usize = 3;
+
+/// Where a car is expected to be when the race ends, assuming everyone keeps their recent pace.
+/// Every car is projected over the full race distance, so a lapped car is projected to finish the
+/// laps it is down rather than when the leader takes the flag, and its gap is too big.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    /// average of the car's recent lap times, in ms
+    pub pace: f32,
+    /// estimated time until this car finishes the race, in ms
+    pub time_to_finish: f32,
+    /// projected finishing position, starting at 1
+    pub position: usize,
+    /// estimated gap to the winner at the flag, in ms
+    pub gap_at_finish: f32,
+    /// lap on which this car would catch the car currently ahead of it, if that happens before the flag
+    pub catches_car_ahead_on_lap: Option<i32>,
+}
+
+/// Average of the most recent completed laps, in ms
+pub fn recent_pace(checkpoints: &Checkpoints) -> Option<f32> {
+    let laps = lap_times(checkpoints);
+    let recent = &laps[laps.len().saturating_sub(RECENT_LAPS)..];
+    if recent.is_empty() {
+        None
+    } else {
+        Some(recent.iter().map(|(_, t)| t).sum::<f32>() / recent.len() as f32)
+    }
+}
+
+/// Projects the finish of a race of `race_laps` laps. Cars start lap 1 when they first cross the
+/// line, so they finish when their progress reaches `race_laps + 1`. Cars that have finished keep
+/// the time they crossed the line and are placed ahead of those still running. Cars whose progress
+/// isn't a number, as when the track length was misread, aren't projected.
+pub fn project(
+    race_laps: i16,
+    race_time: TimeMs,
+    progress: &[f32],
+    checkpoints: &[Checkpoints],
+    gaps_to_leader: &[Option<f32>],
+) -> Vec<Option<Projection>> {
+    let finish = race_laps as f32 + 1.0;
+    let paces: Vec<_> = checkpoints.iter().map(recent_pace).collect();
+    // whether each car has finished, and when it did or is expected to
+    let finishes: Vec<Option<(bool, f32)>> = (0..progress.len())
+        .map(|i| {
+            let pace = paces[i]?;
+            if !progress[i].is_finite() {
+                None
+            } else if progress[i] >= finish {
+                Some((true, time_at_progress(&checkpoints[i], finish)?))
+            } else {
+                Some((false, race_time as f32 + (finish - progress[i]) * pace))
+            }
+        })
+        .collect();
+    let finish_times: Vec<Option<f32>> =
+        finishes.iter().map(|finish| finish.map(|(_, time)| time)).collect();
+    let winner_time = finish_times.iter().flatten().cloned().fold(f32::INFINITY, f32::min);
+
+    let mut running_order: Vec<_> = (0..progress.len()).collect();
+    running_order.sort_by_key(|&i| Reverse(OrderedFloat(progress[i])));
+
+    (0..progress.len())
+        .map(|i| {
+            let pace = paces[i]?;
+            let (finished, finish_time) = finishes[i]?;
+            let ahead_at_finish = |&(other_finished, other_time): &(bool, f32)| {
+                (other_finished, Reverse(OrderedFloat(other_time)))
+                    > (finished, Reverse(OrderedFloat(finish_time)))
+            };
+            let position = 1 + finishes.iter().flatten().filter(|f| ahead_at_finish(f)).count();
+            let place = running_order.iter().position(|&c| c == i).unwrap();
+            let catches_car_ahead_on_lap = if place == 0 {
+                None
+            } else {
+                let ahead = running_order[place - 1];
+                catch_up_lap(
+                    progress[i],
+                    finish,
+                    pace,
+                    paces[ahead],
+                    gaps_to_leader[i],
+                    gaps_to_leader[ahead],
+                )
+            };
+            Some(Projection {
+                pace,
+                time_to_finish: (finish_time - race_time as f32).max(0.0),
+                position,
+                gap_at_finish: finish_time - winner_time,
+                catches_car_ahead_on_lap,
+            })
+        })
+        .collect()
+}
+
+fn catch_up_lap(
+    progress: f32,
+    finish: f32,
+    pace: f32,
+    pace_ahead: Option<f32>,
+    gap_to_leader: Option<f32>,
+    gap_ahead_to_leader: Option<f32>,
+) -> Option<i32> {
+    let gap = gap_to_leader? - gap_ahead_to_leader.unwrap_or(0.0);
+    let closing_per_lap = pace_ahead? - pace;
+    if closing_per_lap <= 0.0 {
+        return None;
+    }
+    let catch_progress = progress + gap / closing_per_lap;
+    if catch_progress < finish {
+        Some(catch_progress.floor() as i32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checkpoints for a car lapping in `lap_ms` from when it first crossed the line at 0 until
+    /// `progress`, ten to a lap
+    fn checkpoints(lap_ms: TimeMs, progress: f32) -> Checkpoints {
+        (10..=(progress * 10.0).round() as i32)
+            .map(|tenth| (OrderedFloat(tenth as f32 / 10.0), (tenth - 10) * lap_ms / 10))
+            .collect()
+    }
+
+    #[test]
+    fn finished_cars_keep_the_order_they_crossed_the_line() {
+        // a 4 lap race ends at progress 5, which the cars reached at 40s, 42s and 44s before
+        // running on past the line
+        let laps = [10_000, 10_500, 11_000];
+        let progress = [5.1; 3];
+        let checkpoints: Vec<_> = laps.iter().map(|&lap| checkpoints(lap, 5.1)).collect();
+        let projections = project(4, 46_000, &progress, &checkpoints, &[None; 3]);
+
+        let result: Vec<_> =
+            projections.iter().map(|p| p.as_ref().map(|p| (p.position, p.gap_at_finish))).collect();
+        assert_eq!(result, vec![Some((1, 0.0)), Some((2, 2_000.0)), Some((3, 4_000.0))]);
+    }
+
+    #[test]
+    fn finished_cars_are_ahead_of_those_still_running() {
+        let progress = [5.1, 4.5];
+        let checkpoints = [checkpoints(10_000, 5.1), checkpoints(10_000, 4.5)];
+        let projections = project(4, 41_000, &progress, &checkpoints, &[None; 2]);
+
+        let result: Vec<_> = projections
+            .iter()
+            .map(|p| p.as_ref().map(|p| (p.position, p.time_to_finish, p.gap_at_finish)))
+            .collect();
+        assert_eq!(result, vec![Some((1, 0.0, 0.0)), Some((2, 5_000.0, 6_000.0))]);
+    }
+}
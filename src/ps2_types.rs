@@ -1,10 +1,183 @@
-use std::marker::PhantomData;
+use std::{
+    any::type_name, collections::HashSet, convert::TryFrom, fmt, fs, io, marker::PhantomData,
+    mem::size_of, ops::Range, path::Path,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use process_memory::{DataMember, ProcessHandle};
 use process_memory::{LocalMember, Memory};
+use serde::Deserialize;
+use thiserror::Error;
 
-const EE_BASE_ADDRESS: u32 = 0x20000000;
+/// Why the PS2's memory couldn't be read. These are expected while the game isn't in a race or
+/// if an address is wrong, so callers should report them rather than give up.
+#[derive(Error, Debug)]
+pub enum Ps2Error {
+    #[error("{address:#010x} is not in any PS2 memory that can be read")]
+    UnmappedAddress { address: u32 },
+    #[error("{size} bytes at {address} run past the end of {}", address.region)]
+    OutOfRange { address: Ps2Address, size: usize },
+    #[error("{region} can't be read from {source_name}")]
+    RegionUnavailable { region: Ps2Region, source_name: &'static str },
+    #[error("null pointer at {address:#010x}, step {step} of pointer chain {chain:x?}")]
+    NullPointer { address: u32, step: usize, chain: Vec<u32> },
+    #[error("null pointer to {pointee}")]
+    Null { pointee: &'static str },
+    #[error("pointers lead back round to {address:#010x}")]
+    Cycle { address: u32 },
+    #[error("{} read from {address:#010x} doesn't look right: {}", pointee, failed.join("; "))]
+    Implausible { address: u32, pointee: &'static str, failed: Vec<String> },
+    #[error("failed to read {size} bytes at {address}")]
+    ReadFailed {
+        address: Ps2Address,
+        size: usize,
+        #[source]
+        source: io::Error,
+    },
+    #[error("not attached to PCSX2")]
+    NotAttached,
+}
+
+pub type Ps2Result<T> = std::result::Result<T, Ps2Error>;
+
+/// The separate memories of the PS2, only some of which the EE can address
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ps2Region {
+    /// EE main memory
+    Ee,
+    /// the EE's 16KB of fast memory
+    Scratchpad,
+    /// the IOP's own memory, used by the sound and disc drivers
+    Iop,
+    Vu0Micro,
+    Vu0Data,
+    Vu1Micro,
+    Vu1Data,
+}
+
+impl Ps2Region {
+    pub fn size(self) -> usize {
+        match self {
+            Ps2Region::Ee => 0x02000000,
+            Ps2Region::Scratchpad => 0x4000,
+            Ps2Region::Iop => 0x200000,
+            Ps2Region::Vu0Micro | Ps2Region::Vu0Data => 0x1000,
+            Ps2Region::Vu1Micro | Ps2Region::Vu1Data => 0x4000,
+        }
+    }
+
+    /// Where PCSX2 1.6 keeps the region in its own address space. Main memory and the
+    /// scratchpad share one reservation, as do the VU memories.
+    fn host_address(self) -> usize {
+        match self {
+            Ps2Region::Ee => 0x20000000,
+            Ps2Region::Scratchpad => 0x22000000,
+            Ps2Region::Iop => 0x24000000,
+            Ps2Region::Vu0Micro => 0x28000000,
+            Ps2Region::Vu0Data => 0x28001000,
+            Ps2Region::Vu1Micro => 0x28002000,
+            Ps2Region::Vu1Data => 0x28006000,
+        }
+    }
+
+    /// Where the EE sees the region, for those it can address other than main memory
+    fn ee_address(self) -> Option<u32> {
+        match self {
+            Ps2Region::Scratchpad => Some(0x70000000),
+            Ps2Region::Vu0Micro => Some(0x11000000),
+            Ps2Region::Vu0Data => Some(0x11004000),
+            Ps2Region::Vu1Micro => Some(0x11008000),
+            Ps2Region::Vu1Data => Some(0x1100C000),
+            Ps2Region::Ee | Ps2Region::Iop => None,
+        }
+    }
+
+    const ALL: [Ps2Region; 7] = [
+        Ps2Region::Ee,
+        Ps2Region::Scratchpad,
+        Ps2Region::Iop,
+        Ps2Region::Vu0Micro,
+        Ps2Region::Vu0Data,
+        Ps2Region::Vu1Micro,
+        Ps2Region::Vu1Data,
+    ];
+}
+
+impl fmt::Display for Ps2Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Ps2Region::Ee => "EE RAM",
+            Ps2Region::Scratchpad => "the scratchpad",
+            Ps2Region::Iop => "IOP RAM",
+            Ps2Region::Vu0Micro => "VU0 micro memory",
+            Ps2Region::Vu0Data => "VU0 data memory",
+            Ps2Region::Vu1Micro => "VU1 micro memory",
+            Ps2Region::Vu1Data => "VU1 data memory",
+        })
+    }
+}
+
+/// An offset into one of the PS2's memories
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ps2Address {
+    pub region: Ps2Region,
+    pub offset: u32,
+}
+
+impl Ps2Address {
+    pub fn new(region: Ps2Region, offset: u32) -> Self {
+        Ps2Address { region, offset }
+    }
+
+    /// Works out which memory an address the EE uses is in, which for main memory may be
+    /// through one of the uncached mirrors
+    pub fn from_ee(address: u32) -> Ps2Result<Self> {
+        let ee = |offset| Ok(Ps2Address::new(Ps2Region::Ee, offset));
+        match address {
+            0x00000000..=0x01FFFFFF => ee(address),
+            0x20000000..=0x21FFFFFF => ee(address - 0x20000000),
+            0x30000000..=0x31FFFFFF => ee(address - 0x30000000),
+            _ => Ps2Region::ALL
+                .iter()
+                .filter_map(|&region| Some((region, region.ee_address()?)))
+                .find(|&(region, base)| (base..base + region.size() as u32).contains(&address))
+                .map(|(region, base)| Ps2Address::new(region, address - base))
+                .ok_or(Ps2Error::UnmappedAddress { address }),
+        }
+    }
+
+    /// Which of the PS2's memories an address in PCSX2 1.6's memory is in, if any
+    pub fn of_host(host_address: usize) -> Option<Self> {
+        Ps2Region::ALL.iter().find_map(|&region| {
+            let base = region.host_address();
+            if (base..base + region.size()).contains(&host_address) {
+                Some(Ps2Address::new(region, (host_address - base) as u32))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Fails if `size` bytes from here don't fit in the region
+    fn check_size(self, size: usize) -> Ps2Result<()> {
+        if self.offset as usize + size > self.region.size() {
+            return Err(Ps2Error::OutOfRange { address: self, size });
+        }
+        Ok(())
+    }
+
+    /// Where `size` bytes from here are in PCSX2 1.6's memory
+    fn host_address(self, size: usize) -> Ps2Result<usize> {
+        self.check_size(size)?;
+        Ok(self.region.host_address() + self.offset as usize)
+    }
+}
+
+impl fmt::Display for Ps2Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x} in {}", self.offset, self.region)
+    }
+}
 
 #[repr(transparent)]
 #[derive(Copy, Clone, Debug)]
@@ -14,34 +187,83 @@ impl<T> Ps2Ptr<T> {
     pub const fn new(offset: u32) -> Self {
         Self(offset, PhantomData)
     }
+
+    pub fn address(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The pointer `bytes` further on, to something else, e.g. a field we have no name for
+    pub fn byte_offset<U>(&self, bytes: u32) -> Ps2Ptr<U> {
+        Ps2Ptr::new(self.0.wrapping_add(bytes))
+    }
+
+    /// The pointer `count` of `T` further on, for a `T` in an array, or None if that is further
+    /// than a pointer can go
+    pub fn add(&self, count: usize) -> Option<Ps2Ptr<T>> {
+        let bytes = count.checked_mul(size_of::<T>())?;
+        Some(self.byte_offset(u32::try_from(bytes).ok()?))
+    }
 }
 
 impl<T: Copy> Ps2Ptr<T> {
-    pub fn get<M: Ps2Memory>(&self, ps2_memory: &M) -> Result<T> {
+    /// Reads what this points to, failing if it is null
+    pub fn get<M: Ps2Memory>(&self, ps2_memory: &M) -> Ps2Result<T> {
+        if self.is_null() {
+            return Err(Ps2Error::Null { pointee: type_name::<T>() });
+        }
         ps2_memory.read(self.0)
     }
 }
 
+impl<T, const N: usize> Ps2Ptr<[T; N]> {
+    /// A pointer to one element, or None past the end of the array
+    pub fn index(&self, index: usize) -> Option<Ps2Ptr<T>> {
+        if index < N {
+            self.byte_offset(0).add(index)
+        } else {
+            None
+        }
+    }
+
+    /// A pointer to each element in turn, as far as a pointer can go
+    pub fn elements(&self) -> impl Iterator<Item = Ps2Ptr<T>> {
+        let first: Ps2Ptr<T> = self.byte_offset(0);
+        (0..N).filter_map(move |i| first.add(i))
+    }
+}
+
 pub struct Ps2PtrChain<T>(Vec<u32>, PhantomData<T>);
 
 impl<T> Ps2PtrChain<T> {
     pub const fn new(offsets: Vec<u32>) -> Self {
         Self(offsets, PhantomData)
     }
+
+    pub fn offsets(&self) -> &[u32] {
+        &self.0
+    }
 }
 
 impl<T: Copy> Ps2PtrChain<T> {
-    pub fn get<M: Ps2Memory>(&self, ps2_memory: &M) -> Result<T> {
+    pub fn get<M: Ps2Memory>(&self, ps2_memory: &M) -> Ps2Result<T> {
         let mut ptr = 0u32;
+        let mut visited = HashSet::new();
         let (&last_offset, offsets) = self.0.split_last().expect("pointer chain has no offsets");
         for (step, &offset) in offsets.iter().enumerate() {
-            let addr = ptr + offset;
-            ptr = ps2_memory.read::<u32>(addr)?;
+            let address = ptr.wrapping_add(offset);
+            ptr = ps2_memory.read::<u32>(address)?;
             if ptr == 0 {
-                bail!("null pointer found at {:x} in chain {:?}[{}]", addr, self.0, step);
+                return Err(Ps2Error::NullPointer { address, step, chain: self.0.clone() });
+            }
+            if !visited.insert(ptr) {
+                return Err(Ps2Error::Cycle { address: ptr });
             }
         }
-        ps2_memory.read(ptr + last_offset)
+        ps2_memory.read(ptr.wrapping_add(last_offset))
     }
 }
 
@@ -50,14 +272,76 @@ impl<T: Copy> Ps2PtrChain<T> {
 #[derive(Copy, Clone, Debug)]
 pub struct Ps2String<const N: usize>([u8; N]);
 
-impl<const N: usize> From<Ps2String<N>> for String {
-    fn from(s: Ps2String<N>) -> Self {
-        return s.0.iter().take_while(|&c| *c != 0).map(|&c| c as char).collect();
+impl<const N: usize> Ps2String<N> {
+    /// The bytes before the terminating zero
+    pub fn bytes(&self) -> &[u8] {
+        let len = self.0.iter().position(|&c| c == 0).unwrap_or(N);
+        &self.0[..len]
     }
 }
 
+impl<const N: usize> From<&str> for Ps2String<N> {
+    fn from(s: &str) -> Self {
+        let mut bytes = [0u8; N];
+        // always leave room for the terminating zero
+        for (b, c) in bytes.iter_mut().take(N - 1).zip(s.bytes()) {
+            *b = c;
+        }
+        Ps2String(bytes)
+    }
+}
+
+/// How the game encodes text, which depends on the region it was released in
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TextEncoding {
+    /// the Japanese release
+    #[serde(rename = "shift_jis")]
+    ShiftJis,
+    /// the American and European releases, which are Latin-1 with a few extra characters
+    #[serde(rename = "windows-1252")]
+    Windows1252,
+}
+
+impl<const N: usize> Ps2String<N> {
+    /// The text, with anything that isn't valid in the encoding replaced by `�`
+    pub fn decode(&self, encoding: TextEncoding) -> String {
+        let encoding = match encoding {
+            TextEncoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            TextEncoding::Windows1252 => encoding_rs::WINDOWS_1252,
+        };
+        encoding.decode_without_bom_handling(self.bytes()).0.into_owned()
+    }
+}
+
+/// Whether the game's memory can be read, for an emulator that may not be running
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    /// always there, such as the emulator's own memory when injected, or an image
+    Local,
+    /// looking for a PCSX2 process to attach to
+    Waiting,
+    Attached {
+        pid: usize,
+        name: String,
+    },
+    /// the PCSX2 chosen by PID or index has exited, and no other is read in its place
+    Lost {
+        pid: usize,
+    },
+}
+
 pub trait Ps2Memory {
-    fn read<T: Copy>(&self, address: u32) -> Result<T>;
+    /// Reads from any of the PS2's memories, failing for those this source can't reach
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T>;
+
+    /// Reads from an address as the EE sees it
+    fn read<T: Copy>(&self, address: u32) -> Ps2Result<T> {
+        self.read_at(Ps2Address::from_ee(address)?)
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        ConnectionStatus::Local
+    }
 }
 
 pub struct Ps2SeparateProcess {
@@ -65,26 +349,106 @@ pub struct Ps2SeparateProcess {
 }
 
 impl Ps2Memory for Ps2SeparateProcess {
-    fn read<T: Copy>(&self, address: u32) -> Result<T> {
-        let mapped_addr = remap_ps2_address(address);
-        Ok(DataMember::new_offset(self.pcsx2_process_handle, vec![mapped_addr as usize]).read()?)
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T> {
+        let host_address = address.host_address(size_of::<T>())?;
+        DataMember::new_offset(self.pcsx2_process_handle, vec![host_address])
+            .read()
+            .map_err(|source| Ps2Error::ReadFailed { address, size: size_of::<T>(), source })
     }
 }
 
-fn remap_ps2_address(address: u32) -> u32 {
-    match address {
-        0x00000000..=0x01FFFFFF => EE_BASE_ADDRESS + address,
-        0x20000000..=0x21FFFFFF => EE_BASE_ADDRESS + address - 0x20000000,
-        0x30000000..=0x31FFFFFF => EE_BASE_ADDRESS + address - 0x30000000,
-        _ => panic!("unsupported PS2 pointer address {:x}", address),
+pub struct Ps2InProcess;
+
+impl Ps2Memory for Ps2InProcess {
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T> {
+        let host_address = address.host_address(size_of::<T>())?;
+        LocalMember::new_offset(vec![host_address]).read().map_err(|source| Ps2Error::ReadFailed {
+            address,
+            size: size_of::<T>(),
+            source,
+        })
     }
 }
 
-pub struct Ps2InProcess;
+/// A copy of EE main memory held in a buffer, for working without a running emulator. The other
+/// memories aren't kept, so reading them fails.
+pub struct Ps2MemoryImage {
+    ee_ram: Vec<u8>,
+}
 
-impl Ps2Memory for Ps2InProcess {
-    fn read<T: Copy>(&self, address: u32) -> Result<T> {
-        let mapped_addr = remap_ps2_address(address);
-        Ok(LocalMember::new_offset(vec![mapped_addr as usize]).read()?)
+const EE_RAM_SIZE: usize = 0x02000000;
+
+impl Ps2MemoryImage {
+    /// Memory with nothing in it
+    pub fn blank() -> Self {
+        Ps2MemoryImage { ee_ram: vec![0; EE_RAM_SIZE] }
+    }
+
+    /// Loads a dump of EE main memory, e.g. one saved from the PCSX2 debugger
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let ee_ram = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        if ee_ram.len() != EE_RAM_SIZE {
+            bail!("{} is {} bytes, expected {}", path.display(), ee_ram.len(), EE_RAM_SIZE);
+        }
+        Ok(Ps2MemoryImage { ee_ram })
+    }
+
+    /// Copies all of EE main memory out of a running emulator
+    pub fn capture(memory: &impl Ps2Memory) -> Result<Self> {
+        const CHUNK_SIZE: usize = 0x10000;
+        let mut ee_ram = Vec::with_capacity(EE_RAM_SIZE);
+        for address in (0..EE_RAM_SIZE).step_by(CHUNK_SIZE) {
+            let chunk = memory
+                .read::<[u8; CHUNK_SIZE]>(address as u32)
+                .with_context(|| format!("reading EE RAM at {:x}", address))?;
+            ee_ram.extend_from_slice(&chunk);
+        }
+        Ok(Ps2MemoryImage { ee_ram })
+    }
+
+    /// Saves the memory in the form `load` reads
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, &self.ee_ram).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// The address of the first occurrence of the bytes
+    pub fn find(&self, needle: &[u8]) -> Option<u32> {
+        if needle.is_empty() {
+            return None;
+        }
+        self.ee_ram.windows(needle.len()).position(|window| window == needle).map(|i| i as u32)
+    }
+
+    fn range(&self, address: Ps2Address, len: usize) -> Ps2Result<Range<usize>> {
+        if address.region != Ps2Region::Ee {
+            return Err(Ps2Error::RegionUnavailable {
+                region: address.region,
+                source_name: "a memory image",
+            });
+        }
+        address.check_size(len)?;
+        let offset = address.offset as usize;
+        Ok(offset..offset + len)
+    }
+
+    pub fn write<T: Copy>(&mut self, address: u32, value: T) -> Ps2Result<()> {
+        let range = self.range(Ps2Address::from_ee(address)?, size_of::<T>())?;
+        unsafe { (self.ee_ram[range].as_mut_ptr() as *mut T).write_unaligned(value) };
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) -> Ps2Result<()> {
+        let range = self.range(Ps2Address::from_ee(address)?, bytes.len())?;
+        self.ee_ram[range].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl Ps2Memory for Ps2MemoryImage {
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T> {
+        let range = self.range(address, size_of::<T>())?;
+        Ok(unsafe { (self.ee_ram[range].as_ptr() as *const T).read_unaligned() })
     }
 }
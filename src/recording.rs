@@ -0,0 +1,154 @@
+use crate::ps2_types::{
+    ConnectionStatus, Ps2Address, Ps2Memory, Ps2MemoryImage, Ps2Region, Ps2Result,
+};
+use anyhow::{bail, Context, Result};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    mem::size_of,
+    path::Path,
+    ptr,
+};
+
+// A recording keeps every read the overlay made of the game, one frame per sample, so a race
+// can be replayed into a memory image later without keeping whole dumps of EE RAM. Reads that
+// got the same bytes as last time are left out, since the image still holds them on replay.
+//
+// Each frame is a little endian u32 count of reads, followed by that many reads of a u32
+// address, a u32 length and the bytes read.
+
+/// The bytes read from each address during one sample
+type Frame = Vec<(u32, Vec<u8>)>;
+
+/// Passes reads through to the memory underneath, keeping what was read to write out
+pub struct Recorder<M: Ps2Memory> {
+    memory: M,
+    reads: RefCell<Frame>,
+    /// what was last written for each address and length
+    written: HashMap<(u32, usize), Vec<u8>>,
+    out: BufWriter<File>,
+}
+
+impl<M: Ps2Memory> Recorder<M> {
+    pub fn create(memory: M, path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        log::info!("recording to {}", path.display());
+        Ok(Recorder {
+            memory,
+            reads: RefCell::new(Vec::new()),
+            written: HashMap::new(),
+            out: BufWriter::new(file),
+        })
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    /// Writes out the reads since the last frame
+    pub fn finish_frame(&mut self) -> Result<()> {
+        let written = &mut self.written;
+        let changed: Frame = self
+            .reads
+            .get_mut()
+            .drain(..)
+            .filter(|(address, bytes)| {
+                written.get(&(*address, bytes.len())).map_or(true, |last| last != bytes)
+            })
+            .collect();
+        self.out.write_all(&(changed.len() as u32).to_le_bytes())?;
+        for (address, bytes) in changed {
+            self.out.write_all(&address.to_le_bytes())?;
+            self.out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            self.out.write_all(&bytes)?;
+            written.insert((address, bytes.len()), bytes);
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl<M: Ps2Memory> Ps2Memory for Recorder<M> {
+    fn read_at<T: Copy>(&self, address: Ps2Address) -> Ps2Result<T> {
+        let value = self.memory.read_at::<T>(address)?;
+        // replays go into a memory image, which only has EE RAM
+        if address.region != Ps2Region::Ee {
+            return Ok(value);
+        }
+        let mut bytes = vec![0u8; size_of::<T>()];
+        unsafe { ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, value) };
+        self.reads.borrow_mut().push((address.offset, bytes));
+        Ok(value)
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        self.memory.connection_status()
+    }
+}
+
+/// A recording loaded back, played one frame per sample and starting over at the end
+pub struct Replay {
+    frames: Vec<Frame>,
+    next: usize,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        let mut frames = Vec::new();
+        loop {
+            let reads = match read_u32(&mut reader) {
+                Ok(reads) => reads,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            let mut frame = Vec::new();
+            for _ in 0..reads {
+                let address = read_u32(&mut reader)?;
+                let len = read_u32(&mut reader)? as usize;
+                // nothing read is bigger than EE RAM, so a bigger length means a corrupt file
+                if len > Ps2Region::Ee.size() {
+                    bail!(
+                        "{} has a read of {} bytes in frame {}",
+                        path.display(),
+                        len,
+                        frames.len()
+                    );
+                }
+                let mut bytes = vec![0u8; len];
+                reader.read_exact(&mut bytes).with_context(|| {
+                    format!("{} ends part way through frame {}", path.display(), frames.len())
+                })?;
+                frame.push((address, bytes));
+            }
+            frames.push(frame);
+        }
+        if frames.is_empty() {
+            bail!("{} has no frames", path.display());
+        }
+        log::info!("replaying {} frames from {}", frames.len(), path.display());
+        Ok(Replay { frames, next: 0 })
+    }
+
+    /// Writes the next frame into the image
+    pub fn apply_next_frame(&mut self, image: &mut Ps2MemoryImage) -> Result<()> {
+        if self.next == self.frames.len() {
+            log::info!("replay finished, starting again");
+            self.next = 0;
+        }
+        for (address, bytes) in &self.frames[self.next] {
+            image.write_bytes(*address, bytes)?;
+        }
+        self.next += 1;
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
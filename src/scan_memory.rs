@@ -10,11 +10,14 @@ use winapi::{
     },
 };
 
+/// The longest needle that can be searched for, since it has to fit in one chunk
+pub const MAX_NEEDLE_LEN: usize = 4096;
+
 /// Finds all occurrences of needle in the private RW memory of the given process.
 pub fn find_all_offsets(needle: &[u8], handle: ProcessHandle) -> Vec<usize> {
-    const CHUNK_SIZE: usize = 4096;
-    if needle.len() > CHUNK_SIZE {
-        panic!("needle too long")
+    const CHUNK_SIZE: usize = MAX_NEEDLE_LEN;
+    if needle.is_empty() || needle.len() > CHUNK_SIZE {
+        panic!("needle must be 1 to {} bytes", CHUNK_SIZE)
     }
 
     // first, figure out which bits of memory are safe to scan
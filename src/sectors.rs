@@ -0,0 +1,136 @@
+use crate::game_data::{time_at_progress, Checkpoints};
+use std::collections::BTreeMap;
+
+const DEFAULT_SECTOR_COUNT: usize = 3;
+
+/// Where each track is split into sectors
+#[derive(Default)]
+pub struct SectorSplits {
+    /// keyed by track length in whole metres, values are the split points strictly between 0 and 1
+    per_track: BTreeMap<u32, Vec<f32>>,
+}
+
+impl SectorSplits {
+    pub fn from_config(sectors: &BTreeMap<String, Vec<f32>>) -> Self {
+        let mut per_track = BTreeMap::new();
+        for (track, splits) in sectors {
+            let track_length: u32 = match track.trim().parse() {
+                Ok(l) => l,
+                Err(_) => {
+                    log::warn!(
+                        "ignoring sectors for {:?}: expected a track length in metres",
+                        track
+                    );
+                    continue;
+                }
+            };
+            if !are_valid_splits(splits) {
+                log::warn!(
+                    "ignoring sectors for track {}: splits must be increasing and between 0 and 1",
+                    track_length
+                );
+                continue;
+            }
+            per_track.insert(track_length, splits.clone());
+        }
+        SectorSplits { per_track }
+    }
+
+    /// Sector boundaries for the given track, starting with 0 and ending with 1.
+    /// Tracks without configured splits get those suggested for the track, or else three equal
+    /// sectors.
+    pub fn boundaries(&self, track_length: f32, suggested: &[f32]) -> Vec<f32> {
+        let metres = track_length.round() as u32;
+        let configured = self
+            .per_track
+            .range(metres.saturating_sub(1)..=metres + 1)
+            .next()
+            .map(|(_, splits)| splits.clone());
+        let splits = configured.unwrap_or_else(|| {
+            if !suggested.is_empty() {
+                return suggested.to_vec();
+            }
+            (1..DEFAULT_SECTOR_COUNT).map(|i| i as f32 / DEFAULT_SECTOR_COUNT as f32).collect()
+        });
+        let mut boundaries = vec![0.0];
+        boundaries.extend(splits);
+        boundaries.push(1.0);
+        boundaries
+    }
+}
+
+/// Whether split points are increasing and strictly between 0 and 1
+pub fn are_valid_splits(splits: &[f32]) -> bool {
+    splits.iter().all(|&s| s > 0.0 && s < 1.0) && splits.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+/// Sector times of a single car, in ms
+#[derive(Clone, Debug)]
+pub struct CarSectors {
+    /// the most recently completed time for each sector
+    pub last: Vec<Option<f32>>,
+    /// the car's best time for each sector
+    pub best: Vec<Option<f32>>,
+}
+
+impl CarSectors {
+    pub fn from_checkpoints(checkpoints: &Checkpoints, boundaries: &[f32]) -> Self {
+        let sector_count = boundaries.len() - 1;
+        let mut sectors =
+            CarSectors { last: vec![None; sector_count], best: vec![None; sector_count] };
+        let (first_lap, last_lap) =
+            match (checkpoints.keys().next(), checkpoints.keys().next_back()) {
+                (Some(first), Some(last)) => {
+                    (first.into_inner().floor() as i32, last.into_inner().floor() as i32)
+                }
+                _ => return sectors,
+            };
+        for lap in first_lap..=last_lap {
+            for sector in 0..sector_count {
+                let start = time_at_progress(checkpoints, lap as f32 + boundaries[sector]);
+                let end = time_at_progress(checkpoints, lap as f32 + boundaries[sector + 1]);
+                if let (Some(start), Some(end)) = (start, end) {
+                    let time = end - start;
+                    sectors.last[sector] = Some(time);
+                    if sectors.best[sector].map_or(true, |best| time < best) {
+                        sectors.best[sector] = Some(time);
+                    }
+                }
+            }
+        }
+        sectors
+    }
+}
+
+/// Best time for each sector across all cars
+pub fn overall_best(cars: &[CarSectors]) -> Vec<Option<f32>> {
+    let sector_count = cars.first().map_or(0, |c| c.best.len());
+    (0..sector_count)
+        .map(|sector| {
+            cars.iter()
+                .filter_map(|c| c.best[sector])
+                .fold(None, |acc: Option<f32>, t| Some(acc.map_or(t, |a| a.min(t))))
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SectorRating {
+    /// fastest of any car
+    OverallBest,
+    /// fastest for this car
+    PersonalBest,
+    Slower,
+}
+
+impl SectorRating {
+    pub fn of(time: f32, personal_best: Option<f32>, overall_best: Option<f32>) -> Self {
+        if overall_best.map_or(false, |best| time <= best) {
+            SectorRating::OverallBest
+        } else if personal_best.map_or(false, |best| time <= best) {
+            SectorRating::PersonalBest
+        } else {
+            SectorRating::Slower
+        }
+    }
+}
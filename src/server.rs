@@ -0,0 +1,311 @@
+use crate::{game_data::RaceState, sinks::RaceSink, tracks::TrackInfo};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tungstenite::Message;
+
+/// connections served at once, each on its own thread
+const MAX_CONNECTIONS: usize = 32;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// only reachable from this machine unless changed
+    pub address: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { address: "127.0.0.1:8089".to_owned() }
+    }
+}
+
+/// One car in the JSON published by the server
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CarSnapshot {
+    pub slot: usize,
+    /// as the game names the car
+    pub name: String,
+    /// as the board shows the car, from the table of cars
+    pub display_name: String,
+    pub manufacturer: Option<String>,
+    pub class: Option<String>,
+    /// `#rrggbb`
+    pub colour: Option<String>,
+    pub is_player: bool,
+    /// false if the car's values look misread, when the rest can't be trusted
+    pub valid: bool,
+    pub position: usize,
+    pub grid_position: Option<usize>,
+    pub lap: i16,
+    pub meters_driven_in_current_lap: f32,
+    /// in ms
+    pub gap_to_leader: Option<f32>,
+    /// in m/s
+    pub speed: Option<f32>,
+    pub gear: u8,
+    pub rpm: f32,
+    pub throttle: f32,
+    pub brake: f32,
+    pub pit_stops: usize,
+    pub in_pit: bool,
+    /// in ms
+    pub last_sectors: Vec<Option<f32>>,
+    pub best_sectors: Vec<Option<f32>>,
+}
+
+/// The JSON published by the server for each sample
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RaceSnapshot {
+    pub race_time: i32,
+    pub track_length: f32,
+    /// name and layout of the track in the table closest in length, which may be wrong since
+    /// tracks are only told apart by length
+    pub track_guess: Option<String>,
+    /// in race order
+    pub cars: Vec<CarSnapshot>,
+    pub best_sectors: Vec<Option<f32>>,
+}
+
+impl From<&RaceState> for RaceSnapshot {
+    fn from(race: &RaceState) -> Self {
+        let mut cars: Vec<_> = race
+            .cars
+            .iter()
+            .enumerate()
+            .map(|(i, car)| CarSnapshot {
+                slot: i,
+                name: race.names[i].clone(),
+                display_name: race.labels[i].name.clone(),
+                manufacturer: race.labels[i].manufacturer.clone(),
+                class: race.labels[i].class.clone(),
+                colour: race.labels[i].colour_hex(),
+                is_player: race.is_player[i],
+                valid: race.validations[i].is_valid(),
+                position: race.positions[i],
+                grid_position: race.grid_positions[i],
+                lap: car.implicit_current_lap(),
+                meters_driven_in_current_lap: car.meters_driven_in_current_lap(),
+                gap_to_leader: race.gaps_to_leader[i],
+                speed: race.speeds[i],
+                gear: car.gear(),
+                rpm: car.rpm(),
+                throttle: car.throttle_pedal(),
+                brake: car.brake1(),
+                pit_stops: race.pits[i].stops,
+                in_pit: race.pits[i].in_pit,
+                last_sectors: race.sectors[i].last.clone(),
+                best_sectors: race.sectors[i].best.clone(),
+            })
+            .collect();
+        cars.sort_by_key(|car| car.position);
+        RaceSnapshot {
+            race_time: race.race_time,
+            track_length: race.track_length,
+            track_guess: race.track_guess.as_ref().map(TrackInfo::full_name),
+            cars,
+            best_sectors: race.best_sectors.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    /// JSON of the most recent sample
+    latest: Mutex<Option<String>>,
+    /// one sender per connected websocket
+    clients: Mutex<Vec<Sender<String>>>,
+    connections: AtomicUsize,
+}
+
+/// Counts a connection for as long as it is open
+struct Connection<'a>(&'a AtomicUsize);
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serves each sample as JSON, streamed over a websocket at any path, or as a snapshot from
+/// `GET /state`
+pub struct RaceServer {
+    shared: Arc<Shared>,
+    address: SocketAddr,
+}
+
+impl RaceServer {
+    pub fn start(config: &ServerConfig) -> Result<RaceServer> {
+        let listener = TcpListener::bind(&config.address)
+            .with_context(|| format!("binding race server to {}", config.address))?;
+        let address = listener.local_addr()?;
+        log::info!("serving race state on {}", address);
+        let shared = Arc::new(Shared::default());
+        let accepting = shared.clone();
+        thread::Builder::new().name("race server".to_owned()).spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if accepting.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                            accepting.connections.fetch_sub(1, Ordering::SeqCst);
+                            log::warn!(
+                                "race server refused a connection, already serving {}",
+                                MAX_CONNECTIONS
+                            );
+                            continue;
+                        }
+                        let shared = accepting.clone();
+                        thread::spawn(move || {
+                            let _connection = Connection(&shared.connections);
+                            if let Err(e) = handle_connection(stream, &shared) {
+                                log::debug!("race server connection closed: {:?}", e);
+                            }
+                        });
+                    }
+                    Err(e) => log::warn!("race server failed to accept: {}", e),
+                }
+            }
+        })?;
+        Ok(RaceServer { shared, address })
+    }
+
+    /// Where the server is listening, with the port chosen if the config's was 0
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl RaceSink for RaceServer {
+    fn publish(&mut self, race: &RaceState) -> Result<()> {
+        let json = serde_json::to_string(&RaceSnapshot::from(race))?;
+        let mut clients = self.shared.clients.lock().unwrap();
+        clients.retain(|client| client.send(json.clone()).is_ok());
+        *self.shared.latest.lock().unwrap() = Some(json);
+        Ok(())
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, shared: &Shared) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    if is_websocket_upgrade(&stream)? {
+        stream.set_read_timeout(None)?;
+        let mut socket = tungstenite::accept(stream).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let (sender, receiver) = channel();
+        {
+            // register under the lock so no sample is missed between the latest and the next
+            let mut clients = shared.clients.lock().unwrap();
+            if let Some(latest) = shared.latest.lock().unwrap().clone() {
+                sender.send(latest)?;
+            }
+            clients.push(sender);
+        }
+        for json in receiver {
+            socket.write_message(Message::Text(json))?;
+        }
+        return Ok(());
+    }
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // skip the rest of the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/state")) => match shared.latest.lock().unwrap().clone() {
+            Some(json) => ("200 OK", json),
+            None => ("503 Service Unavailable", "{\"error\":\"no race yet\"}".to_owned()),
+        },
+        _ => ("404 Not Found", "{\"error\":\"try GET /state or a websocket\"}".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Looks at the request headers without consuming them, so the websocket handshake can read them
+fn is_websocket_upgrade(stream: &TcpStream) -> Result<bool> {
+    let mut buf = [0u8; 4096];
+    for _ in 0..100 {
+        let n = stream.peek(&mut buf)?;
+        let head = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+        if head.contains("\r\n\r\n") || n == buf.len() {
+            return Ok(head.contains("upgrade: websocket"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    bail!("timed out waiting for request headers")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        fake_race,
+        game_data::{GameData, MAX_CARS},
+        ps2_types::Ps2MemoryImage,
+    };
+    use std::io::Read;
+
+    #[test]
+    fn serves_the_fake_race() {
+        let config = ServerConfig { address: "127.0.0.1:0".to_owned() };
+        let server = RaceServer::start(&config).unwrap();
+        let address = server.address().to_string();
+        let mut game_data = GameData::from_image(Ps2MemoryImage::blank(), &Config::default());
+        game_data.sinks.push(Box::new(server));
+        for race_time in (0..100).map(|i| 60_000 + i * 100) {
+            fake_race::write_fake_race(&mut game_data.ps2, race_time).unwrap();
+            game_data.sample_race().unwrap();
+        }
+
+        let snapshot = check_server(&address).unwrap();
+        assert_eq!(snapshot.race_time, 60_000 + 99 * 100);
+        assert_eq!(snapshot.cars.len(), MAX_CARS);
+        assert!(snapshot.cars.iter().all(|car| car.valid));
+        let positions: Vec<_> = snapshot.cars.iter().map(|car| car.position).collect();
+        assert_eq!(positions, (1..=MAX_CARS).collect::<Vec<_>>());
+    }
+
+    /// Fetches a snapshot over REST and the first streamed sample over a websocket, to check a
+    /// running server works end to end
+    fn check_server(address: &str) -> Result<RaceSnapshot> {
+        let mut stream = TcpStream::connect(address)?;
+        write!(stream, "GET /state HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", address)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        if !response.starts_with("HTTP/1.1 200") {
+            bail!("unexpected response to GET /state: {}", response);
+        }
+        let body = response.split("\r\n\r\n").nth(1).context("response has no body")?;
+        let snapshot: RaceSnapshot = serde_json::from_str(body)?;
+        log::info!("GET /state returned {} cars at {}ms", snapshot.cars.len(), snapshot.race_time);
+
+        let stream = TcpStream::connect(address)?;
+        let (mut socket, _) = tungstenite::client(format!("ws://{}/", address), stream)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        match socket.read_message()? {
+            Message::Text(json) => Ok(serde_json::from_str(&json)?),
+            other => bail!("expected a text message, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{
+    config::Config, game_data::RaceState, obs::ObsWriter, server::RaceServer,
+    telemetry::TelemetryLogger, udp::UdpBroadcaster,
+};
+use anyhow::Result;
+
+/// Somewhere every sample of the race is sent, such as a log file
+pub trait RaceSink {
+    fn publish(&mut self, race: &RaceState) -> Result<()>;
+}
+
+/// The sinks enabled in the config
+pub fn from_config(config: &Config) -> Vec<Box<dyn RaceSink + Send>> {
+    let mut sinks: Vec<Box<dyn RaceSink + Send>> = Vec::new();
+    if let Some(telemetry) = &config.telemetry {
+        sinks.push(Box::new(TelemetryLogger::new(telemetry.clone())));
+    }
+    if let Some(server) = &config.server {
+        match RaceServer::start(server) {
+            Ok(server) => sinks.push(Box::new(server)),
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+    if let Some(udp) = &config.udp {
+        match UdpBroadcaster::new(udp.clone()) {
+            Ok(udp) => sinks.push(Box::new(udp)),
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+    if let Some(obs) = &config.obs {
+        match ObsWriter::new(obs.clone()) {
+            Ok(obs) => sinks.push(Box::new(obs)),
+            Err(e) => log::error!("{:?}", e),
+        }
+    }
+    sinks
+}
@@ -0,0 +1,190 @@
+use crate::{
+    game_data::{RaceState, TimeMs},
+    sinks::RaceSink,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryFormat {
+    Csv,
+    /// one JSON object per line
+    Json,
+}
+
+impl FromStr for TelemetryFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(TelemetryFormat::Csv),
+            "json" => Ok(TelemetryFormat::Json),
+            other => bail!("unknown telemetry format {}, try csv or json", other),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub format: TelemetryFormat,
+    /// a new file is started here for every race
+    pub directory: PathBuf,
+    /// samples per second of race time, capped by how often the overlay samples the game
+    pub sample_rate_hz: f32,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            format: TelemetryFormat::Csv,
+            directory: PathBuf::from("telemetry"),
+            sample_rate_hz: 60.0,
+        }
+    }
+}
+
+/// One car in one sample
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TelemetryRow {
+    pub race_time: TimeMs,
+    pub car: usize,
+    pub name: String,
+    /// false if the car's values look misread, when the rest can't be trusted
+    pub valid: bool,
+    pub lap: i16,
+    pub position: usize,
+    pub meters_driven_in_current_lap: f32,
+    /// in m/s
+    pub speed: Option<f32>,
+    pub gap_to_leader: Option<f32>,
+    pub throttle_pedal: f32,
+    pub throttle_actual: f32,
+    pub brake1: f32,
+    pub brake2: f32,
+    pub gear: u8,
+    pub rpm: f32,
+}
+
+/// Reads back a file written by the telemetry logger
+pub fn read_session(path: &Path) -> Result<Vec<TelemetryRow>> {
+    let context = || format!("reading telemetry session {}", path.display());
+    if path.extension().map_or(false, |e| e == "csv") {
+        let mut reader = csv::Reader::from_path(path).with_context(context)?;
+        let rows: Result<Vec<TelemetryRow>, _> = reader.deserialize().collect();
+        Ok(rows.with_context(context)?)
+    } else {
+        let text = fs::read_to_string(path).with_context(context)?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).with_context(context))
+            .collect()
+    }
+}
+
+enum Output {
+    Csv(csv::Writer<File>),
+    Json(BufWriter<File>),
+}
+
+/// Writes every car in every sample to a file, starting a new file whenever a new race starts
+pub struct TelemetryLogger {
+    config: TelemetryConfig,
+    output: Option<Output>,
+    /// race time of the last sample written
+    last_logged: Option<TimeMs>,
+}
+
+impl TelemetryLogger {
+    pub fn new(config: TelemetryConfig) -> Self {
+        TelemetryLogger { config, output: None, last_logged: None }
+    }
+
+    fn start_session(&mut self) -> Result<()> {
+        fs::create_dir_all(&self.config.directory).with_context(|| {
+            format!("creating telemetry directory {}", self.config.directory.display())
+        })?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let extension = match self.config.format {
+            TelemetryFormat::Csv => "csv",
+            TelemetryFormat::Json => "jsonl",
+        };
+        // a race restarted in the same millisecond mustn't overwrite the last one
+        let mut n = 0;
+        let (path, file) = loop {
+            let suffix = if n == 0 { String::new() } else { format!("-{}", n) };
+            let path =
+                self.config.directory.join(format!("gt4-{}{}.{}", started, suffix, extension));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                file => break (path, file),
+            }
+        };
+        let file = file.with_context(|| format!("creating telemetry file {}", path.display()))?;
+        log::info!("logging telemetry to {}", path.display());
+        self.output = Some(match self.config.format {
+            TelemetryFormat::Csv => Output::Csv(csv::Writer::from_writer(file)),
+            TelemetryFormat::Json => Output::Json(BufWriter::new(file)),
+        });
+        Ok(())
+    }
+}
+
+impl RaceSink for TelemetryLogger {
+    fn publish(&mut self, race: &RaceState) -> Result<()> {
+        let interval_ms = (1000.0 / self.config.sample_rate_hz) as TimeMs;
+        match self.last_logged {
+            Some(last) if race.race_time < last => {
+                // the race restarted, so start a new file
+                self.output = None;
+            }
+            Some(last) if race.race_time - last < interval_ms => return Ok(()),
+            _ => {}
+        }
+        if self.output.is_none() {
+            self.start_session()?;
+        }
+        self.last_logged = Some(race.race_time);
+
+        let output = self.output.as_mut().unwrap();
+        for (i, car) in race.cars.iter().enumerate() {
+            let row = TelemetryRow {
+                race_time: race.race_time,
+                car: i,
+                name: race.names[i].clone(),
+                valid: race.validations[i].is_valid(),
+                lap: car.implicit_current_lap(),
+                position: race.positions[i],
+                meters_driven_in_current_lap: car.meters_driven_in_current_lap(),
+                speed: race.speeds[i],
+                gap_to_leader: race.gaps_to_leader[i],
+                throttle_pedal: car.throttle_pedal(),
+                throttle_actual: car.throttle_actual(),
+                brake1: car.brake1(),
+                brake2: car.brake2(),
+                gear: car.gear(),
+                rpm: car.rpm(),
+            };
+            match output {
+                Output::Csv(writer) => writer.serialize(row)?,
+                Output::Json(writer) => {
+                    serde_json::to_writer(&mut *writer, &row)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+        }
+        match output {
+            Output::Csv(writer) => writer.flush()?,
+            Output::Json(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
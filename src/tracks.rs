@@ -0,0 +1,137 @@
+use crate::sectors;
+use anyhow::{Context, Result};
+use ordered_float::OrderedFloat;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// The track isn't known from anything in memory yet, only its length, so tracks are told apart
+// by length. A table of tracks comes with the overlay and more can be added in the config, keyed
+// by length in whole metres like `[sectors]`.
+//
+// That can't tell a track from its reverse layout, or from any other layout of the same length,
+// so only one of them can be in the table. The bundled lengths are the published ones rather than
+// measured in the game, and GT4's own circuits aren't in it yet.
+
+const BUNDLED: &str = include_str!("../resources/tracks.toml");
+
+/// How far a track's length can be from the one in the table, as a fraction of it, since the
+/// game's lengths don't always match the published ones to the metre
+const MATCH_TOLERANCE: f32 = 0.002;
+
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TrackInfo {
+    pub name: String,
+    /// which configuration of the circuit, e.g. "East Course", if it has more than one
+    pub layout: Option<String>,
+    pub country: Option<String>,
+    /// where to split the lap into sectors, as fractions of it, if not set in `[sectors]`
+    pub sectors: Vec<f32>,
+    /// points around the lap as world x and z, for a map of the track
+    pub outline: Vec<[f32; 2]>,
+}
+
+impl TrackInfo {
+    /// The name with the layout, if it has one
+    pub fn full_name(&self) -> String {
+        match &self.layout {
+            Some(layout) => format!("{} {}", self.name, layout),
+            None => self.name.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct TrackTable {
+    tracks: BTreeMap<String, TrackInfo>,
+}
+
+pub struct TrackDatabase {
+    /// keyed by length in metres, those from the config replacing bundled ones of the same length
+    tracks: Vec<(f32, TrackInfo)>,
+}
+
+/// Where a track in the table came from
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Bundled,
+    Config,
+}
+
+impl TrackDatabase {
+    pub fn new(config_tracks: &BTreeMap<String, TrackInfo>) -> Self {
+        let bundled = match parse_table(BUNDLED) {
+            Ok(table) => table,
+            Err(e) => {
+                log::error!("{:#}", e);
+                BTreeMap::new()
+            }
+        };
+        let bundled = bundled.into_iter().map(|(key, track)| (key, track, Source::Bundled));
+        let configured = config_tracks.clone().into_iter().map(|(k, t)| (k, t, Source::Config));
+        let mut by_length = BTreeMap::new();
+        for (key, mut track, source) in bundled.chain(configured) {
+            if !sectors::are_valid_splits(&track.sectors) {
+                log::warn!(
+                    "ignoring sectors for {}: splits must be increasing and between 0 and 1",
+                    key
+                );
+                track.sectors.clear();
+            }
+            let length = match key.trim().parse::<u32>() {
+                Ok(length) => length,
+                Err(_) => {
+                    log::warn!("ignoring track {:?}: expected a track length in metres", key);
+                    continue;
+                }
+            };
+            let name = track.full_name();
+            match by_length.insert(length, (track, source)) {
+                Some((replaced, Source::Bundled)) if source == Source::Config => log::info!(
+                    "{} from the config replaces {}, which is also {}m long",
+                    name,
+                    replaced.full_name(),
+                    length
+                ),
+                Some((replaced, _)) => log::warn!(
+                    "{} and {} are both {}m long, so only {} can be told from its length",
+                    replaced.full_name(),
+                    name,
+                    length,
+                    name
+                ),
+                None => {}
+            }
+        }
+        let tracks: Vec<_> =
+            by_length.into_iter().map(|(length, (track, _))| (length as f32, track)).collect();
+        for pair in tracks.windows(2) {
+            let ((shorter, a), (longer, b)) = (&pair[0], &pair[1]);
+            if longer - shorter <= (shorter + longer) * MATCH_TOLERANCE {
+                log::warn!(
+                    "{} ({}m) and {} ({}m) are too close in length to always be told apart",
+                    a.full_name(),
+                    shorter,
+                    b.full_name(),
+                    longer
+                );
+            }
+        }
+        TrackDatabase { tracks }
+    }
+
+    /// The track closest in length to `track_length`, if any is close enough
+    pub fn identify(&self, track_length: f32) -> Option<&TrackInfo> {
+        self.tracks
+            .iter()
+            .map(|(length, track)| ((length - track_length).abs(), length, track))
+            .filter(|(difference, length, _)| *difference <= *length * MATCH_TOLERANCE)
+            .min_by_key(|(difference, _, _)| OrderedFloat(*difference))
+            .map(|(_, _, track)| track)
+    }
+}
+
+fn parse_table(text: &str) -> Result<BTreeMap<String, TrackInfo>> {
+    let table: TrackTable = toml::from_str(text).context("parsing the bundled table of tracks")?;
+    Ok(table.tracks)
+}
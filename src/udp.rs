@@ -0,0 +1,144 @@
+use crate::{game_data::RaceState, sinks::RaceSink};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+// Sends the player's car in the layout of Forza Motorsport 7's "Data Out" packets, which most
+// dashboard apps and motion rigs understand. Fields GT4 doesn't give us are left as zero.
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpFormat {
+    /// the 232 byte packet with engine and motion data only
+    Sled,
+    /// the 311 byte packet that adds speed, inputs, laps and position
+    Dash,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UdpConfig {
+    pub target: String,
+    pub format: UdpFormat,
+    pub rate_hz: f32,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        UdpConfig { target: "127.0.0.1:5300".to_owned(), format: UdpFormat::Dash, rate_hz: 60.0 }
+    }
+}
+
+pub struct UdpBroadcaster {
+    config: UdpConfig,
+    socket: UdpSocket,
+    interval: Duration,
+    last_sent: Option<Instant>,
+    /// GT4 doesn't tell us the rev limit, so report the highest rpm seen
+    max_rpm: f32,
+}
+
+impl UdpBroadcaster {
+    pub fn new(config: UdpConfig) -> Result<Self> {
+        if !(config.rate_hz > 0.0 && config.rate_hz.is_finite()) {
+            bail!("udp rate_hz must be above 0, not {}", config.rate_hz);
+        }
+        let socket = UdpSocket::bind("0.0.0.0:0").context("binding UDP socket")?;
+        log::info!("sending {:?} packets to {}", config.format, config.target);
+        Ok(UdpBroadcaster {
+            interval: Duration::from_secs_f32(1.0 / config.rate_hz),
+            config,
+            socket,
+            last_sent: None,
+            max_rpm: 0.0,
+        })
+    }
+}
+
+impl RaceSink for UdpBroadcaster {
+    fn publish(&mut self, race: &RaceState) -> Result<()> {
+        if self.last_sent.map_or(false, |last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        let player = match race.is_player.iter().position(|&p| p) {
+            Some(player) => player,
+            None => return Ok(()),
+        };
+        self.last_sent = Some(Instant::now());
+        let car = &race.cars[player];
+        self.max_rpm = self.max_rpm.max(car.rpm());
+
+        let mut packet = ForzaPacket(Vec::with_capacity(311));
+        // sled
+        // race is off while the car looks misread, so dashboards ignore what is sent
+        packet.i32(race.validations[player].is_valid() as i32);
+        packet.u32(race.race_time as u32);
+        packet.f32(self.max_rpm);
+        packet.f32(0.0); // idle rpm
+        packet.f32(car.rpm());
+        packet.zeros(4 * (3 + 3 + 3 + 3)); // acceleration, velocity, angular velocity, orientation
+        packet.zeros(4 * 4 * 9); // suspension, tyre slip, wheel speeds and surfaces for each wheel
+        packet.zeros(4 * 5); // car ordinal, class, performance index, drivetrain, cylinders
+        if self.config.format == UdpFormat::Dash {
+            let laps = &race.laps[player];
+            let seconds = |ms: Option<f32>| ms.unwrap_or(0.0) / 1000.0;
+            packet.zeros(4 * 3); // position
+            packet.f32(race.speeds[player].unwrap_or(0.0));
+            packet.zeros(4 * 2); // power and torque
+            packet.zeros(4 * 4); // tyre temperatures
+            packet.f32(0.0); // boost
+            packet.f32(0.0); // fuel, which hasn't been found in memory
+            packet.f32(car.progress(race.track_length).into_inner().max(0.0) * race.track_length);
+            packet.f32(seconds(laps.best));
+            packet.f32(seconds(laps.last));
+            packet.f32(seconds(laps.current));
+            packet.f32(race.race_time as f32 / 1000.0);
+            packet.u16(car.implicit_current_lap().max(0) as u16);
+            packet.u8(race.positions[player] as u8);
+            packet.u8(pedal(car.throttle_pedal()));
+            packet.u8(pedal(car.brake1()));
+            packet.u8(0); // clutch
+            packet.u8(0); // handbrake
+            packet.u8(car.gear());
+            packet.zeros(3); // steering, driving line, AI brake difference
+        }
+        self.socket.send_to(&packet.0, &self.config.target)?;
+        Ok(())
+    }
+}
+
+fn pedal(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0) as u8
+}
+
+/// Little endian writer for the packet fields
+struct ForzaPacket(Vec<u8>);
+
+impl ForzaPacket {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn zeros(&mut self, n: usize) {
+        self.0.resize(self.0.len() + n, 0);
+    }
+}
@@ -1,22 +1,71 @@
 use imgui::*;
-use std::cmp::Reverse;
 
-use crate::{game_data::GameData, ps2_types::Ps2Memory};
+use crate::{
+    cars::CarLabel,
+    game_data::{format_race_time, GameData, RaceState},
+    player::PlayerSource,
+    positions::Overtake,
+    ps2_types::{ConnectionStatus, Ps2Memory},
+    sectors::SectorRating,
+};
+
+const PURPLE: [f32; 4] = [0.75, 0.35, 1.0, 1.0];
+const GREEN: [f32; 4] = [0.2, 0.9, 0.2, 1.0];
+const YELLOW: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+const PLAYER_HIGHLIGHT: [f32; 4] = [0.4, 0.8, 1.0, 1.0];
+
+/// Latin-1 and the rest of Windows-1252, for car names in the American and European releases
+static LATIN_GLYPHS: [u32; 13] = [
+    0x0020, 0x017F, 0x0192, 0x0192, 0x02C6, 0x02DC, 0x2013, 0x203A, 0x20AC, 0x20AC, 0x2122, 0x2122,
+    0,
+];
+
+/// Fonts with Japanese in them that come with Windows, for car names in the Japanese release
+const JAPANESE_FONTS: [&str; 3] = [
+    "C:\\Windows\\Fonts\\meiryo.ttc",
+    "C:\\Windows\\Fonts\\YuGothM.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+];
 
 pub fn init_ui(imgui: &mut imgui::Context, dpi_factor: f64) {
     let scaled_font_size = (32.0 * dpi_factor) as f32;
-    imgui.fonts().add_font(&[FontSource::TtfData {
+    let mut sources = vec![FontSource::TtfData {
         data: include_bytes!("../resources/texgyreheros-regular.ttf"),
         size_pixels: scaled_font_size,
-        config: None,
-    }]);
+        config: Some(FontConfig {
+            glyph_ranges: FontGlyphRanges::from_slice(&LATIN_GLYPHS),
+            ..FontConfig::default()
+        }),
+    }];
+    // merged into the font above, for the glyphs it doesn't have
+    let japanese = JAPANESE_FONTS.iter().find_map(|path| std::fs::read(path).ok());
+    match &japanese {
+        Some(data) => sources.push(FontSource::TtfData {
+            data,
+            size_pixels: scaled_font_size,
+            config: Some(FontConfig {
+                glyph_ranges: FontGlyphRanges::japanese(),
+                ..FontConfig::default()
+            }),
+        }),
+        None => log::info!("no Japanese font found, so Japanese car names can't be shown"),
+    }
+    imgui.fonts().add_font(&sources);
     imgui.io_mut().font_global_scale = (1.0 / dpi_factor) as f32;
 }
 
+/// Which optional panels are shown, toggled from the options in the timing window
+#[derive(Default)]
+pub struct UiOptions {
+    pub show_projection: bool,
+    pub show_overtakes: bool,
+}
+
 pub fn render_ui<M: Ps2Memory>(
     ui: &Ui,
     window_size: [f32; 2],
     game_data: &mut GameData<M>,
+    options: &mut UiOptions,
     movable: bool,
     scale: f32,
 ) {
@@ -25,30 +74,243 @@ pub fn render_ui<M: Ps2Memory>(
     let styles = ui.push_style_var(StyleVar::WindowRounding(0f32));
     let colors = ui.push_style_color(StyleColor::WindowBg, [0.0, 0.0, 0.0, 0.5]);
 
-    Window::new(im_str!("Timing"))
+    overlay_window(im_str!("Timing"), movable, [0f32, 0f32], window_size).build(ui, || {
+        render_connection_status(ui, &game_data.ps2.connection_status());
+        match &race_state {
+            Ok(r) => {
+                render_track(ui, r);
+                render_board(ui, r);
+            }
+            // usually just that there's no race, but says which read failed if it's more than that
+            Err(e) => ui.text_disabled(im_str!("{:#}", e)),
+        }
+        if CollapsingHeader::new(im_str!("Options")).build(ui) {
+            ui.checkbox(im_str!("Projection"), &mut options.show_projection);
+            ui.checkbox(im_str!("Overtakes"), &mut options.show_overtakes);
+            let mut race_laps = game_data.race_laps.map_or(0, i32::from);
+            if ui.input_int(im_str!("Race laps"), &mut race_laps).build() {
+                game_data.race_laps =
+                    if race_laps > 0 { Some(race_laps.min(i16::MAX as i32) as i16) } else { None };
+            }
+        }
+    });
+
+    if let Ok(r) = &race_state {
+        let sectors_size = [window_size[0], window_size[1] * 0.75];
+        overlay_window(im_str!("Sectors"), movable, [0f32, window_size[1]], sectors_size)
+            .build(ui, || render_sectors(ui, r));
+        if options.show_projection {
+            overlay_window(im_str!("Projection"), movable, [window_size[0], 0f32], window_size)
+                .build(ui, || render_projection(ui, r));
+        }
+        if options.show_overtakes {
+            let position = [window_size[0], window_size[1]];
+            overlay_window(im_str!("Overtakes"), movable, position, window_size)
+                .build(ui, || render_overtakes(ui, r, &game_data.positions.overtakes));
+        }
+    }
+
+    styles.pop(&ui);
+    colors.pop(&ui);
+}
+
+fn overlay_window(name: &ImStr, movable: bool, position: [f32; 2], size: [f32; 2]) -> Window {
+    Window::new(name)
         .title_bar(movable)
         .resizable(movable)
         .movable(movable)
-        .position([0f32, 0f32], Condition::Appearing)
-        .size(window_size, Condition::Appearing)
-        .build(ui, || {
-            if let Ok(r) = race_state {
-                let mut sorted_car_indices: Vec<_> = (0..(r.cars.len())).collect();
-                sorted_car_indices.sort_by_key(|&i| Reverse(r.cars[i].progress(r.track_length)));
-                for i in sorted_car_indices {
-                    let name: String = r.entries[i].car_name_short.into();
-                    let gap_to_leader = r.gaps_to_leader[i].unwrap_or(f32::NAN) / 1000f32;
-                    let text = im_str!(
-                        "+{:.2} {} {}",
-                        gap_to_leader,
-                        ["F", "A", "B", "C", "D", "E"][i], // ugh maybe this assumes the player does not qualify
-                        name
-                    );
-                    ui.text(text);
+        .position(position, Condition::Appearing)
+        .size(size, Condition::Appearing)
+}
+
+/// which PCSX2 is being read, or that it is being waited for
+fn render_connection_status(ui: &Ui, status: &ConnectionStatus) {
+    match status {
+        ConnectionStatus::Local => {}
+        ConnectionStatus::Waiting => ui.text_colored(YELLOW, "Waiting for PCSX2"),
+        ConnectionStatus::Attached { pid, name } => ui.text_disabled(format!("{} ({})", name, pid)),
+        ConnectionStatus::Lost { pid } => {
+            ui.text_colored(YELLOW, format!("PCSX2 {} has exited", pid))
+        }
+    }
+}
+
+/// the name and country of the track guessed from its length, or the length if no track matches
+fn render_track(ui: &Ui, r: &RaceState) {
+    match &r.track_guess {
+        Some(track) => {
+            ui.text(track.full_name());
+            if let Some(country) = &track.country {
+                ui.same_line(0.0);
+                ui.text_disabled(country);
+            }
+            ui.same_line(0.0);
+            ui.text_disabled(format!("(guessed from its length, {:.0}m)", r.track_length));
+        }
+        None => ui.text_disabled(format!("Unknown track, {:.0}m", r.track_length)),
+    }
+}
+
+/// gap to leader, grid position, pit stops and name of each car in race order
+fn render_board(ui: &Ui, r: &RaceState) {
+    let font_size = ui.current_font_size();
+    ui.columns(4, im_str!("board"), false);
+    ui.set_column_width(0, font_size * 3.5);
+    ui.set_column_width(1, font_size * 1.5);
+    ui.set_column_width(2, font_size * 2.0);
+    for i in running_order(r) {
+        let label = &r.labels[i];
+        let gap_to_leader = r.gaps_to_leader[i].unwrap_or(f32::NAN) / 1000f32;
+        let grid = match r.grid_positions[i] {
+            Some(position) => format!("P{}", position),
+            None => "-".to_owned(),
+        };
+        let pits = &r.pits[i];
+        let pit = if pits.in_pit {
+            "PIT".to_owned()
+        } else if pits.stops > 0 {
+            format!("{}S", pits.stops)
+        } else {
+            String::new()
+        };
+        let validation = &r.validations[i];
+        for (column, cell) in
+            [format!("+{:.2}", gap_to_leader), grid, pit, label.name.clone()].iter().enumerate()
+        {
+            if column == 3 {
+                render_swatch(ui, label.colour_rgba());
+            }
+            if !validation.is_valid() {
+                ui.text_disabled(cell);
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(validation.failed.join("\n"));
                 }
+            } else if r.is_player[i] {
+                ui.text_colored(PLAYER_HIGHLIGHT, cell);
+            } else {
+                ui.text(cell);
             }
-        });
+            if column == 3 && validation.is_valid() && ui.is_item_hovered() {
+                ui.tooltip_text(describe_car(label, &r.names[i]));
+            }
+            ui.next_column();
+        }
+    }
+    ui.columns(1, im_str!("board"), false);
+    let invalid = r.validations.iter().filter(|v| !v.is_valid()).count();
+    if invalid > 0 {
+        ui.text_disabled(format!("{} cars look misread, hover for why", invalid));
+    }
+    if r.player_source == PlayerSource::Assumed {
+        ui.text_disabled("player_slot isn't set, so the first car is taken to be the player's");
+        if let Some(hint) = r.player_hint {
+            ui.text_disabled(format!(
+                "car {} looks driven from a pad, set player_slot = {}",
+                hint, hint
+            ));
+        }
+    }
+}
 
-    styles.pop(&ui);
-    colors.pop(&ui);
+/// a square of the car's colour before its name, or a gap the same size if it hasn't one
+fn render_swatch(ui: &Ui, colour: Option<[f32; 4]>) {
+    let size = ui.current_font_size() * 0.8;
+    let [x, y] = ui.cursor_screen_pos();
+    let top = y + (ui.text_line_height() - size) / 2.0;
+    if let Some(colour) = colour {
+        ui.get_window_draw_list()
+            .add_rect([x, top], [x + size, top + size], colour)
+            .filled(true)
+            .build();
+    }
+    ui.dummy([size, size]);
+    ui.same_line(0.0);
+}
+
+/// the game's name for a car and whatever else is known about it
+fn describe_car(label: &CarLabel, game_name: &str) -> String {
+    let mut lines = vec![game_name.to_owned()];
+    lines.extend(label.manufacturer.clone());
+    lines.extend(label.class.clone());
+    lines.join("\n")
+}
+
+/// car indices sorted by position in the race
+fn running_order(r: &RaceState) -> Vec<usize> {
+    let mut sorted_car_indices: Vec<_> = (0..(r.cars.len())).collect();
+    sorted_car_indices.sort_by_key(|&i| r.positions[i]);
+    sorted_car_indices
+}
+
+/// every overtake so far, sticking to the bottom as new ones come in unless scrolled up
+fn render_overtakes(ui: &Ui, r: &RaceState, overtakes: &[Overtake]) {
+    for overtake in overtakes {
+        let overtaking = &r.labels[overtake.overtaking_car].name;
+        let overtaken = &r.labels[overtake.overtaken_car].name;
+        ui.text(im_str!(
+            "{} L{} {} passes {} for P{}",
+            format_race_time(overtake.race_time),
+            overtake.lap,
+            overtaking,
+            overtaken,
+            overtake.new_position
+        ));
+    }
+    if ui.scroll_y() >= ui.scroll_max_y() {
+        ui.set_scroll_here_y_with_ratio(1.0);
+    }
+}
+
+/// last sector times, purple for overall best, green for personal best, yellow otherwise
+fn render_sectors(ui: &Ui, r: &RaceState) {
+    let sector_count = r.best_sectors.len();
+    ui.columns(sector_count as i32 + 1, im_str!("sectors"), false);
+    ui.next_column();
+    for sector in 0..sector_count {
+        ui.text(im_str!("S{}", sector + 1));
+        ui.next_column();
+    }
+    for i in running_order(r) {
+        let name = &r.labels[i].name;
+        ui.text(im_str!("{}", name));
+        ui.next_column();
+        let car_sectors = &r.sectors[i];
+        for sector in 0..sector_count {
+            if let Some(time) = car_sectors.last[sector] {
+                let color = match SectorRating::of(
+                    time,
+                    car_sectors.best[sector],
+                    r.best_sectors[sector],
+                ) {
+                    SectorRating::OverallBest => PURPLE,
+                    SectorRating::PersonalBest => GREEN,
+                    SectorRating::Slower => YELLOW,
+                };
+                ui.text_colored(color, im_str!("{:.3}", time / 1000f32));
+            } else {
+                ui.text("-");
+            }
+            ui.next_column();
+        }
+    }
+    ui.columns(1, im_str!("sectors"), false);
+}
+
+/// projected finishing order, with the lap each car would catch the car ahead of it
+fn render_projection(ui: &Ui, r: &RaceState) {
+    if r.projections.is_empty() {
+        ui.text("set the race laps to project the finish");
+        return;
+    }
+    let mut projected: Vec<_> =
+        r.projections.iter().enumerate().filter_map(|(i, p)| Some((i, p.as_ref()?))).collect();
+    projected.sort_by_key(|(_, p)| p.position);
+    for (i, p) in projected {
+        let name = &r.labels[i].name;
+        let catch_up = match p.catches_car_ahead_on_lap {
+            Some(lap) => format!(" (catches car ahead on lap {})", lap),
+            None => String::new(),
+        };
+        ui.text(im_str!("{} +{:.1} {}{}", p.position, p.gap_at_finish / 1000f32, name, catch_up));
+    }
 }
@@ -0,0 +1,135 @@
+use crate::{
+    game_data::{Automobile, CarSpec, Entry},
+    ps2_types::{Ps2Error, Ps2Memory, Ps2Ptr, Ps2Result, Ps2String},
+};
+use std::any::type_name;
+
+// Structs are read straight out of the emulator's memory, so an address that is wrong for this
+// version of the game, or a race that is still loading, gives nonsense rather than an error.
+// These checks catch values no car could have, so a car can be shown as unreadable instead.
+
+/// higher than any car in the game revs
+const MAX_RPM: f32 = 20000.0;
+/// the most forward gears any car has
+const MAX_GEAR: u8 = 7;
+/// heavier than any car in the game
+const MAX_MASS_KG: f32 = 5000.0;
+/// lighter than any car in the game
+const MIN_MASS_KG: f32 = 100.0;
+
+/// Which checks a car failed, if any
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validation {
+    pub failed: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    fn check(&mut self, ok: bool, failure: impl FnOnce() -> String) {
+        if !ok {
+            self.failed.push(failure());
+        }
+    }
+
+    fn finite(&mut self, name: &str, value: f32) {
+        self.check(value.is_finite(), || format!("{} is {}", name, value));
+    }
+
+    fn in_range(&mut self, name: &str, value: f32, min: f32, max: f32) {
+        self.check(value.is_finite() && value >= min && value <= max, || {
+            format!("{} is {}, expected {} to {}", name, value, min, max)
+        });
+    }
+
+    fn printable<const N: usize>(&mut self, name: &str, s: &Ps2String<N>) {
+        let bytes = s.bytes();
+        self.check(!bytes.is_empty(), || format!("{} is empty", name));
+        // bytes from 0x80 up are left alone, since they may be part of a multibyte character
+        self.check(!bytes.iter().any(|&c| c < 0x20 || c == 0x7f), || {
+            format!("{} has control characters: {:?}", name, String::from_utf8_lossy(bytes))
+        });
+    }
+}
+
+/// A struct that can be checked on its own, without knowing anything else about the race
+pub trait Plausible {
+    fn validate(&self, v: &mut Validation);
+}
+
+impl Plausible for Entry {
+    fn validate(&self, v: &mut Validation) {
+        validate_entry(v, self)
+    }
+}
+
+impl Plausible for CarSpec {
+    fn validate(&self, v: &mut Validation) {
+        v.in_range("mass", self.mass(), MIN_MASS_KG, MAX_MASS_KG);
+    }
+}
+
+impl<T: Copy + Plausible> Ps2Ptr<T> {
+    /// Reads what this points to like `get`, also failing if it doesn't pass its checks
+    pub fn get_valid<M: Ps2Memory>(&self, ps2_memory: &M) -> Ps2Result<T> {
+        let value = self.get(ps2_memory)?;
+        let mut v = Validation::default();
+        value.validate(&mut v);
+        if !v.is_valid() {
+            return Err(Ps2Error::Implausible {
+                address: self.address(),
+                pointee: type_name::<T>(),
+                failed: v.failed,
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// Checks a car, its entry and the car spec it points to, reading the car spec from memory
+pub fn validate_car(
+    ps2: &impl Ps2Memory,
+    car: &Automobile,
+    entry: &Entry,
+    track_length: f32,
+) -> Validation {
+    let mut v = Validation::default();
+    validate_automobile(&mut v, car, track_length);
+    entry.validate(&mut v);
+    match car.car_spec().get_valid(ps2) {
+        Ok(_) => {}
+        Err(Ps2Error::Implausible { failed, .. }) => v.failed.extend(failed),
+        Err(e) => v.failed.push(format!("car spec can't be read: {}", e)),
+    }
+    v
+}
+
+fn validate_automobile(v: &mut Validation, car: &Automobile, track_length: f32) {
+    v.in_range("throttle pedal", car.throttle_pedal(), 0.0, 1.0);
+    v.in_range("throttle", car.throttle_actual(), 0.0, 1.0);
+    for &(name, value) in &[
+        ("brake1", car.brake1()),
+        ("throttle3", car.throttle3()),
+        ("throttle4", car.throttle4()),
+        ("brake2", car.brake2()),
+        ("almost rpm", car.almost_rpm()),
+    ] {
+        v.finite(name, value);
+    }
+    v.in_range("rpm", car.rpm(), 0.0, MAX_RPM);
+    v.check(car.gear() <= MAX_GEAR, || {
+        format!("gear is {}, expected at most {}", car.gear(), MAX_GEAR)
+    });
+    v.in_range("distance into lap", car.meters_driven_in_current_lap(), 0.0, track_length);
+    v.check(car.implicit_current_lap() >= 0, || format!("lap is {}", car.implicit_current_lap()));
+}
+
+fn validate_entry(v: &mut Validation, entry: &Entry) {
+    v.printable("short car name", &entry.car_name_short());
+    v.printable("car name", &entry.car_name());
+    for item in entry.tuning_data().iter() {
+        v.check(item.table().is_some(), || format!("unknown tuning table {}", item.table_id));
+    }
+}